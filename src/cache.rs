@@ -0,0 +1,268 @@
+use crate::tags::{get_tags_with_config, TagConfig, TrackInfo};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A cache of parsed tags, invalidated when the modification time or size of a file changes
+#[derive(Clone, Debug, Default)]
+pub struct TagCache {
+    entries: HashMap<PathBuf, Entry>,
+    changed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    modified: u128,
+    size: u64,
+    config: String,
+    info: TrackInfo,
+}
+
+impl TagCache {
+    pub fn new() -> TagCache {
+        TagCache::default()
+    }
+
+    /// The location of the cache in the cache directory of the user
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join("artistic_shuffle").join("tags.tsv"))
+    }
+
+    /// Load the cache from a file (a missing or unreadable file gives an empty cache)
+    pub fn load(path: &Path) -> TagCache {
+        let mut cache = TagCache::new();
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Some((path, entry)) = parse_entry(&line) {
+                    cache.entries.insert(path, entry);
+                }
+            }
+        }
+        cache
+    }
+
+    /// Write the cache to a file (if anything has changed)
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (path, entry) in self.entries.iter() {
+            // Paths that are not valid UTF-8 are not cached
+            if let Some(path) = path.to_str() {
+                let info = &entry.info;
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    escape(path),
+                    entry.modified,
+                    entry.size,
+                    escape(&info.artist),
+                    info.rating.map(|r| r.to_string()).unwrap_or_default(),
+                    info.genre.as_deref().map(escape).unwrap_or_default(),
+                    info.title.as_deref().map(escape).unwrap_or_default(),
+                    info.duration
+                        .map(|d| d.as_millis().to_string())
+                        .unwrap_or_default(),
+                    info.album.as_deref().map(escape).unwrap_or_default(),
+                    info.album_artist.as_deref().map(escape).unwrap_or_default(),
+                    info.track_number.map(|n| n.to_string()).unwrap_or_default(),
+                    info.play_count.map(|n| n.to_string()).unwrap_or_default(),
+                    info.guessed_artist as u8,
+                    escape(&entry.config),
+                )?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Get the tags from the cache, or read them from the file if they are missing or outdated
+    /// (entries read with different settings, e.g. artist field order, are also outdated)
+    pub fn get_tags(&mut self, path: &Path, config: &TagConfig) -> TrackInfo {
+        match self.lookup(path, config) {
+            Ok(info) => info,
+            Err(key) => {
+                let info = get_tags_with_config(path, config);
+                self.insert(path, key, config, info.clone());
+                info
+            }
+        }
+    }
+
+    /// Get the tags from the cache, or the modification time and size of the file on a miss
+    pub(crate) fn lookup(
+        &self,
+        path: &Path,
+        config: &TagConfig,
+    ) -> std::result::Result<TrackInfo, Option<(u128, u64)>> {
+        let key = file_key(path);
+        match (self.entries.get(path), key) {
+            (Some(entry), Some((modified, size)))
+                if entry.modified == modified
+                    && entry.size == size
+                    && entry.config == config.cache_id() =>
+            {
+                Ok(entry.info.clone())
+            }
+            _ => Err(key),
+        }
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        path: &Path,
+        key: Option<(u128, u64)>,
+        config: &TagConfig,
+        info: TrackInfo,
+    ) {
+        if let Some((modified, size)) = key {
+            self.entries.insert(
+                path.to_path_buf(),
+                Entry {
+                    modified,
+                    size,
+                    config: config.cache_id(),
+                    info,
+                },
+            );
+            self.changed = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn file_key(path: &Path) -> Option<(u128, u64)> {
+    let md = path.metadata().ok()?;
+    let modified = md
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((modified, md.len()))
+}
+
+fn parse_entry(line: &str) -> Option<(PathBuf, Entry)> {
+    let mut fields = line.split('\t');
+    let path = PathBuf::from(unescape(fields.next()?));
+    let modified = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let artist = unescape(fields.next()?);
+    let rating = fields.next()?.parse().ok();
+    let optional = |s: &str| Some(unescape(s)).filter(|s| !s.is_empty());
+    let genre = optional(fields.next()?);
+    let title = optional(fields.next()?);
+    let duration = fields.next()?.parse().ok().map(Duration::from_millis);
+    let album = optional(fields.next()?);
+    let album_artist = optional(fields.next()?);
+    let track_number = fields.next()?.parse().ok();
+    let play_count = fields.next()?.parse().ok();
+    let guessed_artist = fields.next()? == "1";
+    let config = unescape(fields.next()?);
+    let info = TrackInfo {
+        artist,
+        rating,
+        genre,
+        title,
+        duration,
+        album,
+        album_artist,
+        track_number,
+        play_count,
+        guessed_artist,
+    };
+    Some((
+        path,
+        Entry {
+            modified,
+            size,
+            config,
+            info,
+        },
+    ))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => out.push('\t'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_cache");
+        std::fs::create_dir_all(dir.join("Band").join("Album")).unwrap();
+        let file = dir.join("Band").join("Album").join("track.mp3");
+        std::fs::write(&file, b"").unwrap();
+        let config = TagConfig::default();
+        let mut cache = TagCache::new();
+        assert_eq!(cache.get_tags(&file, &config).artist, "Band");
+        assert_eq!(cache.len(), 1);
+        // A hit returns the cached tags without reading the file
+        let key = file_key(&file);
+        let info = TrackInfo {
+            artist: String::from("Cached\tArtist"),
+            rating: Some(200),
+            title: Some(String::from("Title\\")),
+            duration: Some(Duration::from_millis(1234)),
+            album_artist: Some(String::from("Various\nArtists")),
+            track_number: Some(7),
+            play_count: Some(42),
+            guessed_artist: true,
+            ..Default::default()
+        };
+        cache.insert(&file, key, &config, info.clone());
+        assert_eq!(cache.get_tags(&file, &config), info);
+        // Round trip through the file
+        let path = dir.join("cache").join("tags.tsv");
+        cache.save(&path).unwrap();
+        let mut loaded = TagCache::load(&path);
+        assert_eq!(loaded.get_tags(&file, &config), info);
+        // A different artist field order busts the cache
+        let composer = TagConfig {
+            artist_keys: TagConfig::parse_artist_keys("composer").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(loaded.clone().get_tags(&file, &composer).artist, "Band");
+        // A modified file busts the cache
+        std::fs::write(&file, b"modified").unwrap();
+        assert_eq!(loaded.get_tags(&file, &config).artist, "Band");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
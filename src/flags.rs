@@ -0,0 +1,191 @@
+use artistic_shuffle::formats::PathStyle;
+use artistic_shuffle::shuffle::hash_seed;
+use artistic_shuffle::Grouping;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+xflags::xflags! {
+    /// Create a shuffled playlist where no artist is repeated too often and highly rated tracks occur more often.
+    /// The artists and the ratings (4/5 ★ and up) are taken from the files' metadata.
+    /// If metadata is missing then the artist is based on the path (assuming an 'artist/album/track' directory structure, or an 'Artist - Title' filename in flat folders).
+    /// Relative paths are preserved, so make sure that the output is in the correct location.
+    cmd artistic_shuffle {
+        /// Files to add to the playlist (directories are recursively added). The current directory is added if there are no inputs. Use '-' to read a list of files from STDIN.
+        repeated path: PathBuf
+        /// Files with lists to add to the playlist (directories are recursively read, '-' reads from STDIN).
+        repeated -r,--read path: PathBuf
+        /// Files to write the playlist to. The playlist is reshuffled for every output. The playlist is printed to STDOUT if no output is given.
+        /// The format is based on the extension: '.m3u'/'.m3u8' (extended M3U), '.pls', '.xspf', '.json', '.csv', or one path per line.
+        repeated -o,--output path: PathBuf
+        /// Only scan directories N levels deep (1 only adds the files directly in the directories).
+        optional --depth n: usize
+        /// Only add files with this extension when scanning directories (default: mp3, flac, m4a, ogg, opus, wav, aiff, wma).
+        repeated --ext ext: String
+        /// Skip files and directories matching this glob pattern (e.g. 'Podcasts' or 'Audiobooks/**').
+        /// Patterns are also read from a '.shuffleignore' file in the scanned directories.
+        repeated --ignore pattern: String
+        /// Remove duplicate tracks (e.g. from overlapping inputs), keeping every track once with its highest weight.
+        /// A track by multiple artists is then only kept under the first artist.
+        optional --dedupe
+        /// Seed for a reproducible shuffle (every further output gets its own seed derived from it).
+        optional --seed seed: u64
+        /// Seed the shuffle from a string instead (e.g. '--seed-from 2024-06-01' for a playlist that changes daily).
+        optional --seed-from text: String
+        /// What to spread apart: 'artist' (default), 'genre' (genres take precedence over artists),
+        /// 'album' (whole albums are shuffled, keeping the tracks of every album in order),
+        /// or 'folder' (the top-level folders in the input directories are spread apart, ignoring the tags).
+        optional --group grouping: Grouping
+        /// How the paths are written: 'auto' (as given, default), 'absolute', or 'relative' (to the directory of the output).
+        optional --paths style: PathStyle
+        /// How far ahead to look for a different artist when shuffling (default 10).
+        /// A larger lookahead spreads the artists better, but is slower and makes the order less random.
+        optional --lookahead n: usize
+        /// Write extended M3U (with titles and durations) also when the output is not a '.m3u' file.
+        optional --extended
+        /// Only write the first N tracks of the shuffled playlist.
+        optional -n,--limit n: usize
+        /// Only write tracks until the playlist is this many minutes long (the playlist never exceeds the limit).
+        optional --max-duration minutes: u64
+        /// The duration (in minutes) of tracks with an unknown duration when using --max-duration (default 4).
+        optional --default-duration minutes: u64
+        /// Write at most N tracks per file, splitting every output into numbered files ('out.m3u' becomes 'out_001.m3u', 'out_002.m3u', ...).
+        optional --split n: usize
+        /// Split one shuffled playlist into consecutive parts, one for every output, instead of reshuffling for every output.
+        optional --partition
+        /// Let tracks with a low play count (from the tags) occur more often: unplayed tracks occur N+1 times as often, losing one step every time the play count doubles.
+        optional --freshness n: u32
+        /// Skip artists with fewer than N tracks (highly rated tracks count multiple times).
+        optional --min-songs n: usize
+        /// Only include tracks rated at least this many stars (0-5).
+        optional --min-rating stars: u8
+        /// The tag fields to use for the artist, in order of priority (default: trackartist,albumartist,originalartist,performer,composer).
+        optional --artist-key-order keys: String
+        /// Print statistics about the shuffled playlist instead of writing it (dry-run).
+        optional --stats
+        /// Cache the tags (in the user's cache directory) to speed up repeated runs on the same files.
+        optional --cache
+        /// Print progress (the number of files and artists, and the written outputs) to STDERR.
+        optional -v,--verbose
+        /// Print the version.
+        optional -V,--version
+    }
+}
+
+impl Artistic_shuffle {
+    /// The seed from `--seed`, or from hashing the `--seed-from` string (stable across runs and platforms)
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+            .or_else(|| self.seed_from.as_deref().map(hash_seed))
+    }
+
+    /// Parse the arguments, also accepting a bare '-' (STDIN) as an input path
+    pub fn from_args(args: Vec<OsString>) -> xflags::Result<Artistic_shuffle> {
+        Artistic_shuffle::from_vec(args.clone()).or_else(|error| {
+            // xflags treats '-' as an unknown flag unless it comes after '--' (or is a value)
+            let end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+            let is_value = |i: usize| {
+                i > 0
+                    && ["-r", "--read", "-o", "--output"]
+                        .iter()
+                        .any(|f| args[i - 1] == *f)
+            };
+            let (stdin, fixed): (Vec<_>, Vec<_>) = args[..end]
+                .iter()
+                .enumerate()
+                .partition(|(i, a)| *a == "-" && !is_value(*i));
+            let mut fixed: Vec<OsString> = fixed.into_iter().map(|(_, a)| a.clone()).collect();
+            if stdin.is_empty() {
+                return Err(error);
+            }
+            fixed.push("--".into());
+            fixed.extend(stdin.into_iter().map(|(_, a)| a.clone()));
+            fixed.extend(args.into_iter().skip(end + 1));
+            Artistic_shuffle::from_vec(fixed).map_err(|_| error)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Artistic_shuffle {
+        Artistic_shuffle::from_vec(args.iter().map(|a| a.into()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_positional() {
+        let flags = parse(&["a", "-o", "out.m3u", "b", "--read", "list.m3u"]);
+        assert_eq!(flags.path, [PathBuf::from("a"), PathBuf::from("b")]);
+        assert_eq!(flags.output, [PathBuf::from("out.m3u")]);
+        assert_eq!(flags.read, [PathBuf::from("list.m3u")]);
+        // Everything after "--" is an input, even if it looks like a flag
+        let flags = parse(&["a", "--", "-o", "--seed"]);
+        assert_eq!(
+            flags.path,
+            [
+                PathBuf::from("a"),
+                PathBuf::from("-o"),
+                PathBuf::from("--seed")
+            ]
+        );
+        assert!(flags.output.is_empty());
+        assert!(parse(&[]).path.is_empty());
+    }
+
+    #[test]
+    fn test_seed_from() {
+        let date = parse(&["--seed-from", "2024-06-01"]);
+        assert_eq!(date.seed(), Some(hash_seed("2024-06-01")));
+        assert_eq!(date.seed(), parse(&["--seed-from", "2024-06-01"]).seed());
+        assert_ne!(date.seed(), parse(&["--seed-from", "2024-06-02"]).seed());
+        assert_eq!(parse(&["--seed", "1", "--seed-from", "x"]).seed(), Some(1));
+        assert_eq!(parse(&[]).seed(), None);
+        let mut pl = artistic_shuffle::Playlist::new();
+        for i in 0..20 {
+            pl.add(PathBuf::from(i.to_string()), (i % 4).to_string(), None);
+        }
+        let order = |seed| -> Vec<PathBuf> {
+            pl.shuffle_seeded(seed)
+                .nested_iter()
+                .map(|p| p.to_path_buf())
+                .collect()
+        };
+        assert_eq!(order(date.seed().unwrap()), order(hash_seed("2024-06-01")));
+    }
+
+    #[test]
+    fn test_stdin() {
+        let parse = |args: &[&str]| {
+            Artistic_shuffle::from_args(args.iter().map(|a| a.into()).collect()).unwrap()
+        };
+        let flags = parse(&["a", "-", "-o", "out.m3u"]);
+        assert_eq!(flags.path, ["a", "-"].map(PathBuf::from));
+        assert_eq!(flags.output, [PathBuf::from("out.m3u")]);
+        let flags = parse(&["-", "--read", "-", "--", "b"]);
+        assert_eq!(flags.path, ["-", "b"].map(PathBuf::from));
+        assert_eq!(flags.read, [PathBuf::from("-")]);
+        assert!(Artistic_shuffle::from_args(vec!["-".into(), "--bad".into()]).is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let flags = parse(&[
+            "-n", "10", "--seed", "3", "--group", "genre", "-V", "--stats",
+        ]);
+        assert_eq!(flags.limit, Some(10));
+        assert_eq!(flags.seed, Some(3));
+        assert_eq!(flags.group, Some(Grouping::Genre));
+        assert!(flags.version);
+        assert!(flags.stats);
+        assert!(!flags.partition);
+        assert!(!flags.dedupe);
+        assert!(parse(&["--dedupe"]).dedupe);
+        let parse_err = |args: &[&str]| {
+            Artistic_shuffle::from_vec(args.iter().map(|a| a.into()).collect()).is_err()
+        };
+        assert!(parse_err(&["--seed", "x"]));
+        assert!(parse_err(&["--group", "mood"]));
+        assert!(parse_err(&["--unknown"]));
+    }
+}
@@ -0,0 +1,879 @@
+use crate::tags::TrackInfo;
+use std::collections::HashMap;
+use std::io::{Result, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// The output formats, selected by the file extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One path per line
+    Plain,
+    /// Extended M3U with `#EXTINF` lines
+    M3u,
+    /// INI-like PLS with `FileN=` and `TitleN=` entries
+    Pls,
+    /// XML Shareable Playlist Format
+    Xspf,
+    /// JSON array of objects with the path, artist, and rating
+    Json,
+    /// CSV with a `path,artist,rating,weight` header
+    Csv,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Format {
+        match path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("m3u") | Some("m3u8") => Format::M3u,
+            Some("pls") => Format::Pls,
+            Some("xspf") => Format::Xspf,
+            Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            _ => Format::Plain,
+        }
+    }
+}
+
+/// How the paths are written to the playlist
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Write the paths as they were given (relative inputs stay relative to the current directory)
+    #[default]
+    Auto,
+    /// Write absolute paths
+    Absolute,
+    /// Write paths relative to the directory of the playlist
+    Relative,
+}
+
+impl std::str::FromStr for PathStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(PathStyle::Auto),
+            "absolute" => Ok(PathStyle::Absolute),
+            "relative" => Ok(PathStyle::Relative),
+            _ => Err(format!(
+                "Unknown path style '{}' (expected 'auto', 'absolute', or 'relative')",
+                s
+            )),
+        }
+    }
+}
+
+impl PathStyle {
+    /// Convert a path for a playlist in the directory `base`
+    /// (relative paths are relative to the current directory)
+    pub fn apply(self, path: &Path, base: &Path) -> PathBuf {
+        let absolute = |p: &Path| match p.as_os_str().is_empty() {
+            true => std::env::current_dir(),
+            false => std::path::absolute(p),
+        };
+        match self {
+            PathStyle::Auto => path.to_path_buf(),
+            PathStyle::Absolute => absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+            PathStyle::Relative => absolute(path)
+                .and_then(|p| Ok((p, absolute(base)?)))
+                .ok()
+                .and_then(|(p, base)| relative_path(&p, &base))
+                .unwrap_or_else(|| path.to_path_buf()),
+        }
+    }
+}
+
+/// Write the playlist in the given format
+pub fn write<'a, W: Write>(
+    format: Format,
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    match format {
+        Format::Plain => write_plain(writer, entries.map(|(p, _)| p)),
+        Format::M3u => write_m3u_extended(writer, entries),
+        Format::Pls => write_pls(writer, entries),
+        Format::Xspf => write_xspf(writer, entries),
+        Format::Json => write_json(writer, entries),
+        Format::Csv => write_csv(writer, entries),
+    }
+}
+
+/// Write one path per line
+pub fn write_plain<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = &'a PathBuf>,
+) -> Result<()> {
+    for path in entries {
+        writeln!(writer, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Write an extended M3U, tracks without a title or duration only get the path
+pub fn write_m3u_extended<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    writeln!(writer, "#EXTM3U")?;
+    for (path, info) in entries {
+        if let Some(info) = info {
+            if info.title.is_some() || info.duration.is_some() {
+                let seconds = info.duration.map(|d| d.as_secs() as i64).unwrap_or(-1);
+                match &info.title {
+                    Some(title) => {
+                        writeln!(writer, "#EXTINF:{},{} - {}", seconds, info.artist, title)?
+                    }
+                    None => writeln!(writer, "#EXTINF:{},{}", seconds, info.artist)?,
+                }
+            }
+        }
+        writeln!(writer, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Write a PLS playlist, the titles fall back to the file names and unknown lengths are -1
+pub fn write_pls<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    writeln!(writer, "[playlist]")?;
+    let mut n = 0;
+    for (path, info) in entries {
+        n += 1;
+        let title = match info.and_then(|i| i.title.as_deref()) {
+            Some(title) => title.into(),
+            None => path.file_stem().unwrap_or_default().to_string_lossy(),
+        };
+        writeln!(writer, "File{}={}", n, path.display())?;
+        writeln!(writer, "Title{}={}", n, title)?;
+        let length = info
+            .and_then(|i| i.duration)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(-1);
+        writeln!(writer, "Length{}={}", n, length)?;
+    }
+    writeln!(writer, "NumberOfEntries={}", n)?;
+    writeln!(writer, "Version=2")
+}
+
+/// Write an XSPF playlist, relative paths are written as relative URI references
+pub fn write_xspf<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#
+    )?;
+    writeln!(writer, "  <trackList>")?;
+    for (path, info) in entries {
+        writeln!(writer, "    <track>")?;
+        writeln!(
+            writer,
+            "      <location>{}</location>",
+            xml_escape(&path_to_uri(path))
+        )?;
+        if let Some(info) = info {
+            if !info.artist.is_empty() {
+                writeln!(
+                    writer,
+                    "      <creator>{}</creator>",
+                    xml_escape(&info.artist)
+                )?;
+            }
+            if let Some(title) = &info.title {
+                writeln!(writer, "      <title>{}</title>", xml_escape(title))?;
+            }
+        }
+        writeln!(writer, "    </track>")?;
+    }
+    writeln!(writer, "  </trackList>")?;
+    writeln!(writer, "</playlist>")
+}
+
+/// Escape the XML special characters (and remove the characters XML does not allow)
+pub fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Convert a path to a `file://` URI (or a relative URI reference for relative paths)
+pub fn path_to_uri(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    let s = if std::path::MAIN_SEPARATOR == '\\' {
+        s.replace('\\', "/")
+    } else {
+        s.into_owned()
+    };
+    let mut uri = String::with_capacity(s.len() + 8);
+    if path.is_absolute() {
+        uri.push_str("file://");
+        if !s.starts_with('/') {
+            uri.push('/');
+        }
+    }
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            b':' if path.is_absolute() => uri.push(':'),
+            b => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// Write a JSON array with an object (`path`, `artist`, `rating`) for every track
+pub fn write_json<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    write!(writer, "[")?;
+    for (i, (path, info)) in entries.enumerate() {
+        let artist = info.map(|i| i.artist.as_str()).unwrap_or_default();
+        let rating = match info.and_then(|i| i.rating) {
+            Some(rating) => rating.to_string(),
+            None => String::from("null"),
+        };
+        write!(
+            writer,
+            "{}\n  {{\"path\": \"{}\", \"artist\": \"{}\", \"rating\": {}}}",
+            if i == 0 { "" } else { "," },
+            json_escape(&path.to_string_lossy()),
+            json_escape(artist),
+            rating
+        )?;
+    }
+    writeln!(writer, "\n]")
+}
+
+/// Escape a string for use inside JSON quotes
+pub fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write a CSV with a header (`path,artist,rating,weight`) and a row for every track in order,
+/// where the weight is the number of times the track occurs in the list
+pub fn write_csv<'a, W: Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = (&'a PathBuf, Option<&'a TrackInfo>)>,
+) -> Result<()> {
+    let entries: Vec<_> = entries.collect();
+    let mut weights = HashMap::<&PathBuf, usize>::new();
+    for (path, _) in entries.iter() {
+        *weights.entry(*path).or_default() += 1;
+    }
+    let rows = entries.iter().map(|(path, info)| {
+        let artist = info.map(|i| i.artist.as_str()).unwrap_or_default();
+        let rating = info.and_then(|i| i.rating);
+        (path.as_path(), artist, rating, weights[path])
+    });
+    write_csv_rows(writer, true, rows)
+}
+
+/// Write `path,artist,rating,weight` rows (optionally with a header)
+pub fn write_csv_rows<'a, W: Write>(
+    writer: &mut W,
+    header: bool,
+    rows: impl Iterator<Item = (&'a Path, &'a str, Option<u8>, usize)>,
+) -> Result<()> {
+    if header {
+        writeln!(writer, "path,artist,rating,weight")?;
+    }
+    for (path, artist, rating, weight) in rows {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            csv_escape(&path.to_string_lossy()),
+            csv_escape(artist),
+            rating.map(|r| r.to_string()).unwrap_or_default(),
+            weight
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if it contains commas, quotes, or newlines
+pub fn csv_escape(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+/// Split CSV text into records of fields (quoted fields can contain commas, quotes, and newlines)
+pub fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Find the (unescaped) contents of all `<location>` elements in an XSPF playlist
+pub fn xspf_locations(xml: &str) -> Vec<String> {
+    let mut locations = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<location>") {
+        rest = &rest[start + "<location>".len()..];
+        match rest.find("</location>") {
+            Some(end) => {
+                locations.push(xml_unescape(rest[..end].trim()));
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+    locations
+}
+
+/// Replace the XML entities and character references
+pub fn xml_unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let c = entity.and_then(|(e, _)| match e {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match e.strip_prefix("#x").or_else(|| e.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => e.strip_prefix('#')?.parse().ok().and_then(char::from_u32),
+            },
+        });
+        match (c, entity) {
+            (Some(c), Some((_, end))) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Convert a `file://` URI or a relative URI reference to a path (other schemes are unsupported)
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = match uri.split_once(':') {
+        Some((scheme, rest)) if scheme.len() > 1 && !scheme.contains('/') => {
+            if !scheme.eq_ignore_ascii_case("file") {
+                return None;
+            }
+            let rest = rest.strip_prefix("//").unwrap_or(rest);
+            // Skip the host (usually empty or "localhost")
+            let rest = &rest[rest.find('/')?..];
+            // Windows drive letters: "/C:/Music"
+            match rest.as_bytes().get(2) {
+                Some(b':') => &rest[1..],
+                _ => rest,
+            }
+        }
+        _ => uri,
+    };
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Express `path` relative to the directory `base` (e.g. "../b/c.mp3").
+/// Absolute paths are returned as-is when `base` is relative, and `None` is returned when the
+/// path is relative but `base` is absolute (or contains "..").
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    if path.is_absolute() != base.is_absolute() {
+        return Some(path.to_path_buf()).filter(|p| p.is_absolute());
+    }
+    let mut path = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .peekable();
+    let mut base = base
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .peekable();
+    while let (Some(a), Some(b)) = (path.peek(), base.peek()) {
+        if a != b {
+            break;
+        }
+        path.next();
+        base.next();
+    }
+    let mut relative = PathBuf::new();
+    for component in base {
+        match component {
+            Component::ParentDir => return None,
+            _ => relative.push(".."),
+        }
+    }
+    relative.extend(path);
+    Some(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // A writer that fails after a number of bytes (like a full disk)
+    struct FailingWriter(usize);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if self.0 == 0 {
+                return Err(std::io::Error::other("disk full"));
+            }
+            let n = buf.len().min(self.0);
+            self.0 -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_path_style() {
+        let cwd = std::env::current_dir().unwrap();
+        let apply = |style: &str, path: &str, base: &str| {
+            style
+                .parse::<PathStyle>()
+                .unwrap()
+                .apply(Path::new(path), Path::new(base))
+        };
+        let music = if cfg!(windows) { "C:\\music" } else { "/music" };
+        let track = Path::new(music).join("a").join("1.mp3");
+        let lists = Path::new(music).join("lists");
+        let apply_abs = |style: &str| style.parse::<PathStyle>().unwrap().apply(&track, &lists);
+        assert_eq!(apply_abs("auto"), track);
+        assert_eq!(apply_abs("absolute"), track);
+        assert_eq!(
+            apply_abs("relative"),
+            Path::new("..").join("a").join("1.mp3")
+        );
+        assert_eq!(apply("auto", "a/1.mp3", "lists"), PathBuf::from("a/1.mp3"));
+        assert_eq!(apply("Absolute", "a/1.mp3", "lists"), cwd.join("a/1.mp3"));
+        assert_eq!(
+            apply("relative", "a/1.mp3", "lists"),
+            Path::new("..").join("a").join("1.mp3")
+        );
+        assert_eq!(apply("relative", "a/1.mp3", ""), PathBuf::from("a/1.mp3"));
+        assert!("other".parse::<PathStyle>().is_err());
+        assert_eq!(PathStyle::default(), PathStyle::Auto);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let rel = |p: &str, b: &str| relative_path(Path::new(p), Path::new(b));
+        assert_eq!(rel("/music/a/b.mp3", "/music"), Some("a/b.mp3".into()));
+        assert_eq!(
+            rel("/music/a/b.mp3", "/music/c/"),
+            Some("../a/b.mp3".into())
+        );
+        assert_eq!(
+            rel("/music/a/b.mp3", "/other"),
+            Some("../music/a/b.mp3".into())
+        );
+        assert_eq!(rel("a/b.mp3", "./a"), Some("b.mp3".into()));
+        assert_eq!(rel("a/b.mp3", ""), Some("a/b.mp3".into()));
+        assert_eq!(rel("/a/b.mp3", "c"), Some("/a/b.mp3".into()));
+        assert_eq!(rel("a/b.mp3", "/c"), None);
+        assert_eq!(rel("a/b.mp3", "../c"), None);
+    }
+
+    #[test]
+    fn test_failing_writer() {
+        let paths: Vec<_> = (0..100)
+            .map(|i| PathBuf::from(format!("track{}.mp3", i)))
+            .collect();
+        let info = TrackInfo::default();
+        for format in [
+            Format::Plain,
+            Format::M3u,
+            Format::Pls,
+            Format::Xspf,
+            Format::Json,
+            Format::Csv,
+        ] {
+            for limit in [0, 10, 100] {
+                let entries = paths.iter().map(|p| (p, Some(&info)));
+                let err = write(format, &mut FailingWriter(limit), entries).unwrap_err();
+                assert_eq!(err.to_string(), "disk full");
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::from_path(Path::new("a/b.M3U8")), Format::M3u);
+        assert_eq!(Format::from_path(Path::new("a/b.m3u")), Format::M3u);
+        assert_eq!(Format::from_path(Path::new("a/b.pls")), Format::Pls);
+        assert_eq!(Format::from_path(Path::new("a/b.xspf")), Format::Xspf);
+        assert_eq!(Format::from_path(Path::new("a/b.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("a/b.csv")), Format::Csv);
+        assert_eq!(Format::from_path(Path::new("a/b.txt")), Format::Plain);
+        assert_eq!(Format::from_path(Path::new("a")), Format::Plain);
+    }
+
+    #[test]
+    fn test_pls() {
+        let info = TrackInfo {
+            title: Some(String::from("Title")),
+            duration: Some(Duration::from_secs(200)),
+            ..Default::default()
+        };
+        let paths = [PathBuf::from("a/b.mp3"), PathBuf::from("c/d.mp3")];
+        let entries = [(&paths[0], Some(&info)), (&paths[1], None)];
+        let file = std::env::temp_dir().join("artistic_shuffle_test_pls.pls");
+        write(
+            Format::from_path(&file),
+            &mut std::fs::File::create(&file).unwrap(),
+            entries.into_iter(),
+        )
+        .unwrap();
+        let pls = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        let lines: Vec<_> = pls.lines().collect();
+        assert_eq!(lines.first(), Some(&"[playlist]"));
+        assert!(lines.contains(&"File1=a/b.mp3"));
+        assert!(lines.contains(&"Title1=Title"));
+        assert!(lines.contains(&"File2=c/d.mp3"));
+        assert!(lines.contains(&"Length1=200"));
+        assert!(lines.contains(&"Title2=d"));
+        assert!(lines.contains(&"Length2=-1"));
+        assert!(lines.contains(&"NumberOfEntries=2"));
+        assert!(lines.contains(&"Version=2"));
+    }
+
+    #[test]
+    fn test_json() {
+        let a = TrackInfo {
+            artist: String::from("A \"B\""),
+            rating: Some(200),
+            ..Default::default()
+        };
+        let paths = [PathBuf::from("a\\b.mp3"), PathBuf::from("c.mp3")];
+        let entries = [(&paths[0], Some(&a)), (&paths[1], None)];
+        let mut out = Vec::new();
+        write(Format::Json, &mut out, entries.into_iter()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "[\n",
+                "  {\"path\": \"a\\\\b.mp3\", \"artist\": \"A \\\"B\\\"\", \"rating\": 200},\n",
+                "  {\"path\": \"c.mp3\", \"artist\": \"\", \"rating\": null}\n",
+                "]\n"
+            )
+        );
+        let mut out = Vec::new();
+        write(Format::Json, &mut out, std::iter::empty()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[\n]\n");
+        assert_eq!(json_escape("\u{1}\t"), "\\u0001\\t");
+    }
+
+    /// Parse a JSON array of flat objects (with string, number, or null values)
+    fn parse_json_objects(text: &str) -> Vec<Vec<(String, Option<String>)>> {
+        fn skip_ws(chars: &mut std::iter::Peekable<std::str::Chars>) {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+        }
+        fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+            assert_eq!(chars.next(), Some('"'));
+            let mut out = String::new();
+            loop {
+                match chars.next().unwrap() {
+                    '"' => return out,
+                    '\\' => match chars.next().unwrap() {
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            let c = u32::from_str_radix(&hex, 16).unwrap();
+                            out.push(char::from_u32(c).unwrap());
+                        }
+                        c => out.push(c),
+                    },
+                    c => out.push(c),
+                }
+            }
+        }
+        let mut chars = text.chars().peekable();
+        let mut objects = Vec::new();
+        skip_ws(&mut chars);
+        assert_eq!(chars.next(), Some('['));
+        loop {
+            skip_ws(&mut chars);
+            match chars.next() {
+                Some(']') => break,
+                Some(',') => continue,
+                Some('{') => {}
+                c => panic!("unexpected {:?}", c),
+            }
+            let mut object = Vec::new();
+            loop {
+                skip_ws(&mut chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                    break;
+                }
+                if chars.peek() == Some(&',') {
+                    chars.next();
+                    continue;
+                }
+                let key = string(&mut chars);
+                skip_ws(&mut chars);
+                assert_eq!(chars.next(), Some(':'));
+                skip_ws(&mut chars);
+                let value = if chars.peek() == Some(&'"') {
+                    Some(string(&mut chars))
+                } else {
+                    let mut value = String::new();
+                    while chars
+                        .peek()
+                        .is_some_and(|c| c.is_alphanumeric() || *c == '-')
+                    {
+                        value.push(chars.next().unwrap());
+                    }
+                    Some(value).filter(|v| v != "null")
+                };
+                object.push((key, value));
+            }
+            objects.push(object);
+        }
+        skip_ws(&mut chars);
+        assert_eq!(chars.next(), None);
+        objects
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut pl = crate::Playlist::new();
+        for i in 0..12 {
+            let artist = ["A \"B\"", "C\\D", "É\u{1}"][i % 3];
+            let path = PathBuf::from(format!("{}/{}.mp3", i % 3, i));
+            pl.add(path, String::from(artist), Some(200).filter(|_| i % 2 == 0));
+        }
+        let shuffled = pl.shuffle_seeded(5);
+        let tracks: Vec<_> = shuffled.nested_iter().copied().collect();
+        let mut out = Vec::new();
+        let entries = tracks.iter().map(|p| (*p, pl.track(p)));
+        write(Format::Json, &mut out, entries).unwrap();
+        let objects = parse_json_objects(std::str::from_utf8(&out).unwrap());
+        assert_eq!(objects.len(), tracks.len());
+        for (object, path) in objects.iter().zip(tracks.iter()) {
+            let info = pl.track(path).unwrap();
+            let field = |key: &str| object.iter().find(|(k, _)| k == key).unwrap().1.clone();
+            assert_eq!(field("path"), Some(path.to_string_lossy().to_string()));
+            assert_eq!(field("artist"), Some(info.artist.clone()));
+            assert_eq!(field("rating"), info.rating.map(|r| r.to_string()));
+        }
+        // Every artist keeps all of their tracks
+        let mut expected = HashMap::new();
+        for path in pl.songs() {
+            *expected
+                .entry(pl.track(path).unwrap().artist.clone())
+                .or_insert(0) += 1;
+        }
+        let mut artists = HashMap::new();
+        for object in objects.iter() {
+            *artists.entry(object[1].1.clone().unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(artists, expected);
+    }
+
+    #[test]
+    fn test_csv() {
+        let a = TrackInfo {
+            artist: String::from("Crosby, Stills & \"Nash\""),
+            rating: Some(255),
+            ..Default::default()
+        };
+        let paths = [PathBuf::from("a,b/c.mp3"), PathBuf::from("d.mp3")];
+        let entries = [
+            (&paths[0], Some(&a)),
+            (&paths[1], None),
+            (&paths[0], Some(&a)),
+        ];
+        let file = std::env::temp_dir().join("artistic_shuffle_test_csv.csv");
+        write(
+            Format::from_path(&file),
+            &mut std::fs::File::create(&file).unwrap(),
+            entries.into_iter(),
+        )
+        .unwrap();
+        let csv = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        let records = csv_records(&csv);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0], ["path", "artist", "rating", "weight"]);
+        assert_eq!(
+            records[1],
+            ["a,b/c.mp3", "Crosby, Stills & \"Nash\"", "255", "2"]
+        );
+        assert_eq!(records[2], ["d.mp3", "", "", "1"]);
+        assert_eq!(records[3], records[1]);
+        assert_eq!(
+            csv_records("a,\"b\nc\"\r\nd"),
+            [vec!["a", "b\nc"], vec!["d"]]
+        );
+    }
+
+    #[test]
+    fn test_xspf() {
+        let info = TrackInfo {
+            artist: String::from("Motörhead & <Friends>"),
+            title: Some(String::from("Ace \"of\" Spades")),
+            ..Default::default()
+        };
+        let paths = [
+            PathBuf::from("/music/a b/ü.mp3"),
+            PathBuf::from("c:d/e.mp3"),
+        ];
+        let entries = [(&paths[0], Some(&info)), (&paths[1], None)];
+        let mut out = Vec::new();
+        write(Format::Xspf, &mut out, entries.into_iter()).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.starts_with("<?xml"));
+        assert_eq!(xml.matches("<track>").count(), 2);
+        assert!(xml.contains("<creator>Motörhead &amp; &lt;Friends&gt;</creator>"));
+        assert!(xml.contains("<title>Ace &quot;of&quot; Spades</title>"));
+        assert!(xml.contains("<location>file:///music/a%20b/%C3%BC.mp3</location>"));
+        // Well-formed: every element is closed in the right order
+        let mut stack = Vec::new();
+        for tag in xml.split('<').skip(2).map(|t| &t[..t.find('>').unwrap()]) {
+            match tag.strip_prefix('/') {
+                Some(name) => assert_eq!(stack.pop(), Some(name)),
+                None => stack.push(tag.split(' ').next().unwrap()),
+            }
+        }
+        assert!(stack.is_empty());
+        // The locations can be read back
+        let locations: Vec<_> = xspf_locations(&xml)
+            .iter()
+            .map(|l| uri_to_path(l).unwrap())
+            .collect();
+        assert_eq!(locations, paths);
+    }
+
+    #[test]
+    fn test_xspf_locations() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <trackList>
+    <track><location>file:///music/A%20%26%20B/song.mp3</location><title>x</title></track>
+    <track>
+      <location>
+        rel/R&amp;B/s%C3%A5ng.flac
+      </location>
+    </track>
+    <track><location>http://example.com/stream</location></track>
+  </trackList>
+</playlist>"#;
+        let locations = xspf_locations(xml);
+        assert_eq!(locations.len(), 3);
+        assert_eq!(locations[1], "rel/R&B/s%C3%A5ng.flac");
+        let paths: Vec<_> = locations.iter().map(|l| uri_to_path(l)).collect();
+        assert_eq!(paths[0], Some(PathBuf::from("/music/A & B/song.mp3")));
+        assert_eq!(paths[1], Some(PathBuf::from("rel/R&B/sång.flac")));
+        assert_eq!(paths[2], None);
+        assert_eq!(
+            uri_to_path("file://localhost/C:/a.mp3"),
+            Some(PathBuf::from("C:/a.mp3"))
+        );
+        assert_eq!(xml_unescape("&lt;&#65;&#x42;&bogus;&"), "<AB&bogus;&");
+    }
+
+    #[test]
+    fn test_m3u() {
+        let info = TrackInfo {
+            artist: String::from("Artist"),
+            title: Some(String::from("Title")),
+            duration: Some(Duration::from_millis(61500)),
+            ..Default::default()
+        };
+        let untitled = TrackInfo {
+            artist: String::from("Artist"),
+            ..Default::default()
+        };
+        let paths = [PathBuf::from("a.mp3"), PathBuf::from("b.mp3")];
+        let mut out = Vec::new();
+        let entries = [(&paths[0], Some(&info)), (&paths[1], Some(&untitled))];
+        write(Format::M3u, &mut out, entries.into_iter()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "#EXTM3U\n#EXTINF:61,Artist - Title\na.mp3\nb.mp3\n"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use std::path::{Component, Path};
+
+/// The name of the file (in a scanned directory) with additional patterns to ignore
+pub const IGNORE_FILE: &str = ".shuffleignore";
+
+/// Glob patterns for files and directories to skip when scanning directories.
+///
+/// Patterns without a '/' match the name of any file or directory (e.g. `Podcasts` or `*.txt`),
+/// while patterns with a '/' match the path relative to the scanned directory
+/// (e.g. `Audiobooks/*` or `**/Live/*.mp3`).
+/// `*` and `?` do not match '/', but `**` does.
+#[derive(Clone, Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<String>,
+}
+
+impl Ignore {
+    pub fn new() -> Ignore {
+        Ignore::default()
+    }
+
+    /// Add a pattern (empty patterns and comments starting with '#' are skipped)
+    pub fn add(&mut self, pattern: &str) {
+        let pattern = pattern.trim();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return;
+        }
+        let pattern = pattern.trim_end_matches('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        self.patterns.push(pattern.replace('\\', "/"));
+    }
+
+    /// Add the patterns from a file (one per line)
+    pub fn read(&mut self, path: &Path) -> Result<()> {
+        for line in BufReader::new(File::open(path)?).lines() {
+            self.add(&line?);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check if a path (relative to the scanned directory) matches any pattern
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let parts: Vec<Vec<char>> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy().chars().collect()),
+                _ => None,
+            })
+            .collect();
+        let full = parts.join(&'/');
+        self.patterns.iter().any(|pattern| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            if pattern.contains(&'/') {
+                glob_match(&pattern, &full)
+            } else {
+                parts.last().is_some_and(|name| glob_match(&pattern, name))
+            }
+        })
+    }
+}
+
+/// Match a glob pattern (`*`, `**`, and `?`) against a '/' separated path
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', ['*', rest @ ..])) => {
+            // "**/" also matches zero directories
+            let zero_dirs = match rest.split_first() {
+                Some(('/', rest)) => glob_match(rest, text),
+                _ => false,
+            };
+            zero_dirs || (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(('*', rest)) => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        Some(('?', rest)) => match text.split_first() {
+            Some((c, text)) => *c != '/' && glob_match(rest, text),
+            None => false,
+        },
+        Some((p, rest)) => match text.split_first() {
+            Some((c, text)) => c == p && glob_match(rest, text),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let matches = |p: &str, t: &str| {
+            glob_match(
+                &p.chars().collect::<Vec<_>>(),
+                &t.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("Podcasts", "Podcasts"));
+        assert!(!matches("Podcasts", "Podcast"));
+        assert!(matches("*.txt", "notes.txt"));
+        assert!(!matches("*.txt", "dir/notes.txt"));
+        assert!(matches("track?.mp3", "track1.mp3"));
+        assert!(!matches("a?b", "a/b"));
+        assert!(matches("Audiobooks/*", "Audiobooks/Book"));
+        assert!(!matches("Audiobooks/*", "Audiobooks/Book/01.mp3"));
+        assert!(matches("**/Live", "Band/Album/Live"));
+        assert!(matches("**/Live", "Live"));
+        assert!(matches("Band/**", "Band/Album/01.mp3"));
+        assert!(matches("*", ""));
+        assert!(!matches("?", ""));
+    }
+
+    #[test]
+    fn test_ignore() {
+        let mut ignore = Ignore::new();
+        assert!(!ignore.is_ignored(Path::new("anything")));
+        ignore.add("# comment");
+        ignore.add("  ");
+        ignore.add("Podcasts/");
+        ignore.add("/Audiobooks/*");
+        ignore.add("*.txt");
+        assert_eq!(ignore.len(), 3);
+        assert!(ignore.is_ignored(Path::new("Podcasts")));
+        assert!(ignore.is_ignored(Path::new("Band/Podcasts")));
+        assert!(!ignore.is_ignored(Path::new("Band/Podcasts.mp3")));
+        assert!(ignore.is_ignored(Path::new("Audiobooks/Book")));
+        assert!(!ignore.is_ignored(Path::new("Audiobooks")));
+        assert!(!ignore.is_ignored(Path::new("Band/Audiobooks/Book")));
+        assert!(ignore.is_ignored(Path::new("Band/Album/notes.txt")));
+        assert!(!ignore.is_ignored(Path::new("Band/Album/01.mp3")));
+    }
+}
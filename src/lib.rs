@@ -0,0 +1,1994 @@
+//! Shuffle music so that the same artist is not played (too often) in a row.
+//!
+//! ```no_run
+//! use artistic_shuffle::Playlist;
+//! use std::path::PathBuf;
+//!
+//! let mut pl = Playlist::new();
+//! pl.add_path(PathBuf::from("Music"));
+//! for track in pl.shuffle().nested_iter() {
+//!     println!("{}", track.display());
+//! }
+//! ```
+
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::{DirEntry, WalkDir};
+
+pub mod cache;
+pub mod formats;
+pub mod ignore;
+pub mod shuffle;
+pub mod tags;
+
+pub use cache::TagCache;
+pub use ignore::Ignore;
+pub use shuffle::{Counter, ShuffleTree, Shuffler};
+pub use tags::{get_tags, TagConfig, TrackInfo};
+
+/// Statistics about how well the artists are spread in a shuffled playlist
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of tracks per artist (highly rated tracks count multiple times)
+    pub artists: Vec<(String, usize)>,
+    /// The total number of tracks (highly rated tracks count multiple times)
+    pub total: usize,
+    /// The longest run of consecutive tracks by the same artist
+    pub max_run: usize,
+    /// The smallest distance between two tracks by the same artist (`None` if no artist repeats)
+    pub min_gap: Option<usize>,
+}
+
+/// How the tracks are grouped before the groups are spread apart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Grouping {
+    /// Spread the artists apart
+    #[default]
+    Artist,
+    /// Spread the genres apart, and the artists within each genre
+    Genre,
+    /// Shuffle whole albums (keeping the tracks in order) and spread the artists' albums apart
+    Album,
+    /// Spread the top-level folders (below the scanned directories) apart, ignoring the tags
+    Folder,
+}
+
+impl std::str::FromStr for Grouping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "artist" => Ok(Grouping::Artist),
+            "genre" => Ok(Grouping::Genre),
+            "album" => Ok(Grouping::Album),
+            "folder" => Ok(Grouping::Folder),
+            _ => Err(format!(
+                "Unknown grouping '{}' (expected 'artist', 'genre', 'album', or 'folder')",
+                s
+            )),
+        }
+    }
+}
+
+/// How far ahead the shuffle looks for a different artist by default
+pub const DEFAULT_LOOKAHEAD: usize = 10;
+
+/// The extensions of the files that are added when scanning directories (by default)
+pub const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "flac", "m4a", "ogg", "opus", "wav", "aiff", "wma"];
+
+/// A collection of tracks grouped by artist, ready to be shuffled
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    artists: HashMap<String, Counter<PathBuf>>,
+    tracks: HashMap<PathBuf, TrackInfo>,
+    cache: Option<TagCache>,
+    config: TagConfig,
+    ignore: Ignore,
+    extensions: Vec<String>,
+    freshness: u32,
+    max_depth: Option<usize>,
+    lookahead: usize,
+    /// The highest weight each track has been added with (for deduplication)
+    max_weights: HashMap<PathBuf, usize>,
+}
+
+impl Playlist {
+    pub fn new() -> Playlist {
+        Playlist {
+            artists: HashMap::new(),
+            tracks: HashMap::new(),
+            cache: None,
+            config: TagConfig::default(),
+            ignore: Ignore::new(),
+            extensions: AUDIO_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+            freshness: 0,
+            max_depth: None,
+            lookahead: DEFAULT_LOOKAHEAD,
+            max_weights: HashMap::new(),
+        }
+    }
+
+    /// Add a track with known artist and rating
+    pub fn add(&mut self, file: PathBuf, artist: String, rating: Option<u8>) {
+        self.add_track(
+            file,
+            TrackInfo {
+                artist,
+                rating,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Add a track with known tags
+    pub fn add_track(&mut self, file: PathBuf, info: TrackInfo) {
+        let times = track_weight(&info, self.freshness);
+        self.add_track_times(file, info, times);
+    }
+
+    /// Add a track with known tags and a custom weight (instead of the weight from the rating)
+    pub fn add_track_weighted(&mut self, file: PathBuf, info: TrackInfo, weight: usize) {
+        if weight > 0 {
+            self.add_track_times(file, info, weight);
+        }
+    }
+
+    fn add_track_times(&mut self, file: PathBuf, info: TrackInfo, times: usize) {
+        let compilation = info
+            .album_artist
+            .as_deref()
+            .filter(|a| self.config.is_various_artists(a));
+        let mut artists = match compilation {
+            // Keep compilations together as one group
+            Some(album_artist) => vec![info.album.as_deref().unwrap_or(album_artist)],
+            None => tags::split_artists(&info.artist, &self.config.artist_separators),
+        };
+        if artists.is_empty() {
+            artists.push(&info.artist);
+        }
+        if !self.config.all_artists {
+            artists.truncate(1);
+        }
+        let max = self.max_weights.entry(file.clone()).or_default();
+        *max = std::cmp::max(*max, times);
+        for artist in artists {
+            let artist = artist_key(artist);
+            match self.artists.get_mut(&artist) {
+                Some(counter) => counter.addn(file.clone(), times),
+                None => {
+                    let mut counter = Counter::new();
+                    counter.addn(file.clone(), times);
+                    self.artists.insert(artist, counter);
+                }
+            }
+        }
+        self.tracks.insert(file, info);
+    }
+
+    /// The number of tracks in the playlist (highly rated tracks count multiple times)
+    pub fn len(&self) -> usize {
+        self.artists.values().map(|c| c.total()).sum()
+    }
+
+    /// The number of distinct files in the playlist
+    pub fn file_count(&self) -> usize {
+        let files: HashSet<_> = self
+            .artists
+            .values()
+            .flat_map(|c| c.iter())
+            .map(|(p, _)| p)
+            .collect();
+        files.len()
+    }
+
+    /// The files in the playlist where the artist was guessed from the path (the tags have no artist)
+    pub fn guessed_files(&self) -> Vec<&PathBuf> {
+        let mut files: Vec<_> = self
+            .artists
+            .values()
+            .flat_map(|c| c.iter())
+            .map(|(p, _)| p)
+            .filter(|p| self.tracks.get(*p).is_some_and(|t| t.guessed_artist))
+            .collect();
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+
+    /// The number of distinct artists in the playlist
+    pub fn artist_count(&self) -> usize {
+        self.artists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.artists.is_empty()
+    }
+
+    /// The (normalised) names of the artists in the playlist
+    pub fn artists(&self) -> impl Iterator<Item = &str> {
+        self.artists.keys().map(|a| a.as_str())
+    }
+
+    /// The tracks (and their weights) of an artist
+    pub fn get_counter(&self, artist: &str) -> Option<&Counter<PathBuf>> {
+        self.artists.get(&artist_key(artist))
+    }
+
+    /// Remove an artist and their tracks from the playlist
+    pub fn remove_artist(&mut self, artist: &str) -> Option<Counter<PathBuf>> {
+        self.artists.remove(&artist_key(artist))
+    }
+
+    /// Add the artists and tracks of another playlist
+    pub fn merge(&mut self, other: Playlist) {
+        for (artist, counter) in other.artists {
+            self.artists.entry(artist).or_default().merge(counter);
+        }
+        self.tracks.extend(other.tracks);
+        for (file, weight) in other.max_weights {
+            let max = self.max_weights.entry(file).or_default();
+            *max = std::cmp::max(*max, weight);
+        }
+    }
+
+    /// Remove duplicate tracks (e.g. from adding the same directory twice).
+    /// Every track keeps the highest weight it was added with, and (unless all artists of a track are used)
+    /// a track that is listed under multiple artists is kept only under the first artist (sorted by name).
+    pub fn deduplicate(&mut self) {
+        let mut seen = HashSet::new();
+        let mut artists: Vec<_> = self.artists.keys().cloned().collect();
+        artists.sort_unstable();
+        for artist in artists {
+            let counter = self.artists.get_mut(&artist).unwrap();
+            let mut deduped = Counter::new();
+            for (file, times) in counter.iter() {
+                if self.config.all_artists || seen.insert(file.clone()) {
+                    let weight = self.max_weights.get(file).map_or(*times, |w| *w);
+                    deduped.addn(file.clone(), weight.min(*times));
+                }
+            }
+            if deduped.total() == 0 {
+                self.artists.remove(&artist);
+            } else {
+                *counter = deduped;
+            }
+        }
+    }
+
+    /// Remove the artists with fewer than `min` tracks (highly rated tracks count multiple times)
+    pub fn filter_by_min_songs(&mut self, min: usize) {
+        self.artists.retain(|_, c| c.total() >= min);
+    }
+
+    /// Remove the tracks rated below `min` stars (unrated tracks count as zero stars)
+    pub fn filter_by_rating(&mut self, min: u8) {
+        let tracks = &self.tracks;
+        let stars = |p: &PathBuf| {
+            tracks
+                .get(p)
+                .and_then(|t| t.rating)
+                .map_or(0, tags::rating_to_stars)
+        };
+        for counter in self.artists.values_mut() {
+            let remove: Vec<PathBuf> = counter
+                .iter()
+                .map(|(p, _)| p)
+                .filter(|p| stars(p) < min)
+                .cloned()
+                .collect();
+            for p in remove.iter() {
+                counter.remove(p);
+            }
+        }
+        self.artists.retain(|_, c| c.total() > 0);
+    }
+
+    /// Calculate statistics for a shuffled playlist (e.g. from [`Playlist::shuffle`])
+    pub fn stats(&self, tracks: &[&PathBuf]) -> Stats {
+        let mut artists: Vec<_> = self
+            .artists
+            .iter()
+            .map(|(a, c)| (a.clone(), c.total()))
+            .collect();
+        artists.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut lookup: HashMap<&PathBuf, usize> = HashMap::new();
+        for (i, (artist, _)) in artists.iter().enumerate() {
+            for (path, _) in self.artists[artist].iter() {
+                lookup.entry(path).or_insert(i);
+            }
+        }
+        let mut last = vec![None; artists.len()];
+        let mut min_gap = None;
+        let mut max_run = 0;
+        let mut run = 0;
+        for (i, track) in tracks.iter().enumerate() {
+            let artist = match lookup.get(track) {
+                Some(a) => *a,
+                None => {
+                    run = 0;
+                    continue;
+                }
+            };
+            match last[artist] {
+                Some(j) if j + 1 == i => run += 1,
+                _ => run = 1,
+            }
+            if let Some(j) = last[artist] {
+                min_gap = Some(min_gap.map_or(i - j, |g: usize| g.min(i - j)));
+            }
+            max_run = max_run.max(run);
+            last[artist] = Some(i);
+        }
+        Stats {
+            total: artists.iter().map(|(_, n)| n).sum(),
+            artists,
+            max_run,
+            min_gap,
+        }
+    }
+
+    /// All tracks in the playlist (highly rated tracks are repeated, in no particular order)
+    pub fn songs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.artists
+            .values()
+            .flat_map(|c| c.iter().flat_map(|(p, n)| std::iter::repeat_n(p, *n)))
+    }
+
+    /// Get the tags of a track in the playlist
+    pub fn track(&self, file: &Path) -> Option<&TrackInfo> {
+        self.tracks.get(file)
+    }
+
+    /// Change how the tags are interpreted (affects tracks added afterwards)
+    pub fn set_config(&mut self, config: TagConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &TagConfig {
+        &self.config
+    }
+
+    /// Use a cache when reading tags
+    pub fn set_cache(&mut self, cache: TagCache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn cache(&self) -> Option<&TagCache> {
+        self.cache.as_ref()
+    }
+
+    /// Let tracks that have been played less often occur more often (affects tracks added afterwards).
+    /// Unplayed tracks get `freshness + 1` times the weight, losing one step every time the play count doubles.
+    pub fn set_freshness(&mut self, freshness: u32) {
+        self.freshness = freshness;
+    }
+
+    /// How far ahead the shuffle looks for a different artist when the next one is the same
+    /// (default [`DEFAULT_LOOKAHEAD`]). A larger lookahead spreads the artists better, but takes
+    /// more work and makes the order less random.
+    pub fn set_lookahead(&mut self, lookahead: usize) {
+        self.lookahead = lookahead;
+    }
+
+    /// Limit how deep directories are scanned (1 only scans the files directly in the directory)
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Skip files and directories matching these patterns when scanning directories
+    /// (in addition to the patterns in a `.shuffleignore` file in the scanned directory)
+    pub fn set_ignore(&mut self, ignore: Ignore) {
+        self.ignore = ignore;
+    }
+
+    /// Only add files with these extensions when scanning directories (all files if empty).
+    /// Defaults to [`AUDIO_EXTENSIONS`].
+    pub fn set_extensions(&mut self, extensions: Vec<String>) {
+        self.extensions = extensions
+            .iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect();
+    }
+
+    /// List the files in a directory (recursively), skipping hidden and ignored files
+    fn walk_dir(&self, path: &Path, max_depth: Option<usize>) -> Vec<PathBuf> {
+        let mut ignore = self.ignore.clone();
+        let ignore_file = path.join(ignore::IGNORE_FILE);
+        if ignore_file.is_file() {
+            if let Err(error) = ignore.read(&ignore_file) {
+                eprintln!(
+                    "Could not read ignore file '{}': {}",
+                    ignore_file.display(),
+                    error
+                );
+            }
+        }
+        let keep = |e: &DirEntry| {
+            let is_hidden = e
+                .file_name()
+                .to_str()
+                .map(|s| s.starts_with('.'))
+                .unwrap_or(false);
+            let is_ignored = e
+                .path()
+                .strip_prefix(path)
+                .map(|p| ignore.is_ignored(p))
+                .unwrap_or(false);
+            e.depth() == 0 || !(is_hidden || is_ignored)
+        };
+        let mut files = Vec::new();
+        for entry in WalkDir::new(path)
+            .follow_links(true)
+            .max_depth(max_depth.unwrap_or(usize::MAX))
+            .into_iter()
+            .filter_entry(keep)
+        {
+            match entry {
+                Ok(entry) if entry.file_type().is_dir() => {}
+                Ok(entry) => files.push(entry.into_path()),
+                Err(error) => eprintln!("Could not access file: {}", error),
+            }
+        }
+        files
+    }
+
+    /// Read the tags of a file (from the cache if possible)
+    fn get_tags(&mut self, file: &Path) -> TrackInfo {
+        match self.cache.as_mut() {
+            Some(cache) => cache.get_tags(file, &self.config),
+            None => tags::get_tags_with_config(file, &self.config),
+        }
+    }
+
+    /// Add the path to the playlist (recursively if it is a directory)
+    pub fn add_path(&mut self, path: PathBuf) {
+        if path.is_dir() {
+            self.add_dir(path)
+        } else {
+            self.add_file(path)
+        }
+    }
+
+    pub fn add_file(&mut self, file: PathBuf) {
+        let info = self.get_tags(&file);
+        self.add_track(file, info);
+    }
+
+    /// Add a file with a different output path
+    pub fn add_file2(&mut self, file: &Path, path: PathBuf) {
+        let info = self.get_tags(file);
+        self.add_track(path, info);
+    }
+
+    fn add_dir(&mut self, path: PathBuf) {
+        let mut files = self.walk_dir(&path, self.max_depth);
+        files.retain(|f| has_extension(f, &self.extensions));
+        self.add_files(files, &path);
+    }
+
+    /// Add a directory recursively, but only the files with one of the given extensions
+    /// (case-insensitive, e.g. `&["mp3", "flac"]`) instead of the default extensions
+    pub fn add_dir_with_extensions(&mut self, path: PathBuf, extensions: &[&str]) {
+        let extensions: Vec<_> = extensions
+            .iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect();
+        let mut files = self.walk_dir(&path, self.max_depth);
+        files.retain(|f| has_extension(f, &extensions));
+        self.add_files(files, &path);
+    }
+
+    /// Add a directory, but only recurse `max_depth` levels (1 only adds the files directly in it)
+    pub fn add_dir_with_depth(&mut self, path: PathBuf, max_depth: usize) {
+        let mut files = self.walk_dir(&path, Some(max_depth));
+        files.retain(|f| has_extension(f, &self.extensions));
+        self.add_files(files, &path);
+    }
+
+    /// Read the tags of the files in parallel (using the cache if possible) and add them
+    /// (guessing missing artists from the paths relative to the scanned directory `root`)
+    fn add_files(&mut self, files: Vec<PathBuf>, root: &Path) {
+        let config = &self.config;
+        let infos = match self.cache.as_mut() {
+            None => read_tags(&files, config),
+            Some(cache) => {
+                let lookups: Vec<_> = files.iter().map(|f| cache.lookup(f, config)).collect();
+                let misses: Vec<PathBuf> = files
+                    .iter()
+                    .zip(lookups.iter())
+                    .filter(|(_, l)| l.is_err())
+                    .map(|(f, _)| f.clone())
+                    .collect();
+                let mut read = misses.iter().zip(read_tags(&misses, config));
+                lookups
+                    .into_iter()
+                    .filter_map(|lookup| match lookup {
+                        Ok(info) => Some(info),
+                        Err(key) => {
+                            let (file, info) = read.next()?;
+                            cache.insert(file, key, config, info.clone());
+                            Some(info)
+                        }
+                    })
+                    .collect()
+            }
+        };
+        for (file, mut info) in files.into_iter().zip(infos) {
+            if info.guessed_artist {
+                let separator = &self.config.filename_separator;
+                info.artist = tags::guess_artist(&file, Some(root), separator);
+            }
+            self.add_track(file, info);
+        }
+    }
+
+    /// Read the contents of the file and add to the playlist (recursively if it is a directory)
+    pub fn read_path(&mut self, path: PathBuf) {
+        match path.metadata() {
+            Ok(md) => {
+                if md.is_dir() {
+                    self.read_dir(path)
+                } else if md.is_file() {
+                    self.read_file(&path)
+                } else {
+                    eprintln!("Unknown type of object: {}", path.to_string_lossy())
+                }
+            }
+            Err(e) => eprintln!("Error accessing path '{}': {}", path.to_string_lossy(), e),
+        }
+    }
+
+    /// Read and add files from a file (e.g. playlist)
+    fn read_file(&mut self, file: &Path) {
+        match file
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("pls") => return self.read_pls(file),
+            Some("xspf") => return self.read_xspf(file),
+            Some("csv") => return self.read_csv(file, "path"),
+            _ => {}
+        }
+        if let Ok(f) = File::open(file) {
+            self.read_from_reader(BufReader::new(f), file.parent());
+        }
+    }
+
+    /// Add the files listed in a reader (one path per line, or an M3U playlist, e.g. from STDIN).
+    /// Relative paths are resolved from `base_dir` (or the current directory).
+    /// Empty lines and comments starting with '#' are skipped, but `#EXTINF` lines fill in the
+    /// title and duration of the next file if its tags lack them.
+    pub fn read_from_reader<R: BufRead>(&mut self, reader: R, base_dir: Option<&Path>) {
+        let mut extinf = None;
+        for line in raw_lines(reader) {
+            let line = line.trim_ascii();
+            if let Some(info) = line.strip_prefix(b"#EXTINF:") {
+                extinf = Some(parse_extinf(&String::from_utf8_lossy(info)));
+            } else if !line.is_empty() && !line.starts_with(b"#") {
+                let Some(path) = bytes_to_path(line.to_vec()) else {
+                    continue;
+                };
+                let mut info = match base_dir {
+                    Some(base_dir) if !path.is_absolute() => self.get_tags(&base_dir.join(&path)),
+                    _ => self.get_tags(&path),
+                };
+                if let Some((duration, title)) = extinf.take() {
+                    info.duration = info.duration.or(duration);
+                    info.title = info.title.or(title);
+                }
+                self.add_track(path, info);
+            }
+        }
+    }
+
+    /// Read and add files from a PLS playlist (only the `FileN=` entries are used)
+    fn read_pls(&mut self, file: &Path) {
+        let parent = file.parent();
+        if let Ok(f) = File::open(file) {
+            for line in BufReader::new(f).lines().map_while(Result::ok) {
+                if let Some((key, value)) = line.split_once('=') {
+                    let key = key.trim();
+                    let is_file = key.get(..4).map(|k| k.eq_ignore_ascii_case("file"));
+                    let is_numbered = key.len() > 4 && key[4..].bytes().all(|b| b.is_ascii_digit());
+                    if is_file == Some(true) && is_numbered {
+                        self.add_listed(parent, PathBuf::from(value.trim()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read and add files from an XSPF playlist (only local files are supported)
+    fn read_xspf(&mut self, file: &Path) {
+        let parent = file.parent();
+        match std::fs::read_to_string(file) {
+            Ok(xml) => {
+                for location in formats::xspf_locations(&xml) {
+                    match formats::uri_to_path(&location) {
+                        Some(path) => self.add_listed(parent, path),
+                        None => {
+                            eprintln!("Unsupported location in '{}': {}", file.display(), location)
+                        }
+                    }
+                }
+            }
+            Err(e) => eprintln!("Could not read playlist '{}': {}", file.display(), e),
+        }
+    }
+
+    /// Read and add files from a CSV with a header row. The paths are read from the given
+    /// column (or the first column if there is no such column). Optional `artist` and `rating`
+    /// (0-255) columns override the tags.
+    pub fn read_csv(&mut self, file: &Path, column: &str) {
+        match File::open(file) {
+            Ok(f) => self.read_csv_from_reader(BufReader::new(f), file.parent(), column),
+            Err(e) => eprintln!("Could not read playlist '{}': {}", file.display(), e),
+        }
+    }
+
+    /// Read a CSV (e.g. written by [`Playlist::write_csv`]) and add the listed tracks.
+    /// With a header the paths are in `column` (and the optional `artist`, `rating`, and
+    /// `weight` columns are used), without a header only the first column (the paths) is used.
+    /// A track with a weight is only added once, even if it is listed multiple times.
+    /// Relative paths are resolved from `base_dir` (or the current directory).
+    pub fn read_csv_from_reader<R: BufRead>(
+        &mut self,
+        mut reader: R,
+        base_dir: Option<&Path>,
+        column: &str,
+    ) {
+        let mut csv = String::new();
+        if let Err(e) = reader.read_to_string(&mut csv) {
+            return eprintln!("Could not read CSV: {}", e);
+        }
+        let mut records = formats::csv_records(&csv).into_iter().peekable();
+        let find = |header: &[String], name: &str| {
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        };
+        let (path_col, artist_col, rating_col, weight_col) = match records.peek() {
+            Some(header) if find(header, column).is_some() => {
+                let cols = (
+                    find(header, column),
+                    find(header, "artist"),
+                    find(header, "rating"),
+                    find(header, "weight"),
+                );
+                records.next();
+                cols
+            }
+            _ => (Some(0), None, None, None),
+        };
+        let mut weighted = HashSet::new();
+        for record in records {
+            let field = |col: Option<usize>| {
+                col.and_then(|c| record.get(c))
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+            };
+            let path = match field(path_col) {
+                Some(path) => PathBuf::from(path),
+                None => continue,
+            };
+            let weight = field(weight_col).and_then(|w| w.parse().ok());
+            if weight.is_some() && !weighted.insert(path.clone()) {
+                continue;
+            }
+            let mut info = match base_dir {
+                Some(base_dir) if !path.is_absolute() => self.get_tags(&base_dir.join(&path)),
+                _ => self.get_tags(&path),
+            };
+            if let Some(artist) = field(artist_col) {
+                info.artist = String::from(artist);
+            }
+            if let Some(rating) = field(rating_col).and_then(|r| r.parse().ok()) {
+                info.rating = Some(rating);
+            }
+            match weight {
+                Some(weight) => self.add_track_weighted(path, info, weight),
+                None => self.add_track(path, info),
+            }
+        }
+    }
+
+    /// Write a CSV with a `path,artist,rating,weight` row for every track of every artist
+    /// (sorted by artist), so that the weights can be edited and read back
+    /// (the header is needed for reading the artists and weights).
+    /// This is the same format as the `.csv` output of a shuffled playlist.
+    pub fn write_csv<W: Write>(&self, writer: &mut W, header: bool) -> std::io::Result<()> {
+        let mut artists: Vec<_> = self.artists.iter().collect();
+        artists.sort_unstable_by_key(|(a, _)| *a);
+        let mut rows = Vec::with_capacity(self.tracks.len());
+        for (key, counter) in artists {
+            let mut tracks: Vec<_> = counter.iter().collect();
+            tracks.sort_unstable();
+            for (path, weight) in tracks {
+                // Prefer the artist as tagged, unless the track is listed under another artist
+                let info = self.track(path);
+                let artist = match info {
+                    Some(info) if artist_key(&info.artist) == *key => info.artist.as_str(),
+                    _ => key.as_str(),
+                };
+                let rating = info.and_then(|i| i.rating);
+                rows.push((path.as_path(), artist, rating, *weight));
+            }
+        }
+        formats::write_csv_rows(writer, header, rows.into_iter())
+    }
+
+    /// Add a file from a playlist (relative paths are relative to the playlist)
+    fn add_listed(&mut self, parent: Option<&Path>, path: PathBuf) {
+        match parent {
+            Some(parent) if !path.is_absolute() => self.add_file2(&parent.join(&path), path),
+            _ => self.add_file(path),
+        }
+    }
+
+    fn read_dir(&mut self, path: PathBuf) {
+        for file in self.walk_dir(&path, self.max_depth) {
+            self.read_file(&file);
+        }
+    }
+
+    /// Create a list of all tracks in the playlist with an artist-aware shuffle
+    pub fn shuffle(&self) -> Shuffler<Shuffler<&PathBuf>> {
+        self.shuffle_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Create a list of all tracks in the playlist with an artist-aware shuffle using the given
+    /// random number generator
+    pub fn shuffle_with_rng<R: Rng + ?Sized>(&self, rng: &mut R) -> Shuffler<Shuffler<&PathBuf>> {
+        let mut ts = shuffle::Shuffler::new();
+        for (_, ts2) in self.shufflers() {
+            ts.nested_add(ts2);
+        }
+        ts.nested_shuffle_with_rng(rng, self.lookahead);
+        ts
+    }
+
+    /// Create a list of all tracks in the playlist with a reproducible artist-aware shuffle.
+    /// The seed is mixed with the artist names, so adding an artist keeps the order of the
+    /// tracks within every other artist unchanged.
+    pub fn shuffle_seeded(&self, seed: u64) -> Shuffler<Shuffler<&PathBuf>> {
+        let mut ts = shuffle::Shuffler::new();
+        for (artist, mut ts2) in self.shufflers() {
+            ts2.shuffle_seeded(seed ^ shuffle::hash_seed(artist), self.lookahead);
+            ts.nested_add(ts2);
+        }
+        ts.shuffle_seeded(seed, self.lookahead);
+        ts
+    }
+
+    /// Write a shuffled playlist (one path per line) to any writer.
+    /// With a `base_dir` the paths are written relative to that directory (when possible).
+    pub fn write_to_writer<W: Write>(
+        &self,
+        writer: &mut W,
+        base_dir: Option<&Path>,
+    ) -> std::io::Result<()> {
+        let shuffled = self.shuffle();
+        for path in shuffled.nested_iter() {
+            match base_dir.and_then(|base| formats::relative_path(path, base)) {
+                Some(relative) => writeln!(writer, "{}", relative.display())?,
+                None => writeln!(writer, "{}", path.display())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Take tracks until the next one would make the total duration exceed `max`.
+    /// Tracks with an unknown duration are assumed to be `default` long.
+    pub fn take_duration<'a, 'b>(
+        &'b self,
+        tracks: impl Iterator<Item = &'a PathBuf> + 'b,
+        max: Duration,
+        default: Duration,
+    ) -> impl Iterator<Item = &'a PathBuf> + 'b {
+        let mut total = Duration::ZERO;
+        tracks.take_while(move |p| {
+            total += self.track(p).and_then(|t| t.duration).unwrap_or(default);
+            total <= max
+        })
+    }
+
+    /// Create a list of all tracks in the playlist with a genre-aware and artist-aware shuffle.
+    /// Genres take precedence: the genres are spread apart first and then the artists are
+    /// spread apart within each genre, so neighbours with the same genre have different artists.
+    pub fn shuffle_by_genre(&self) -> Shuffler<Shuffler<&PathBuf>> {
+        self.shuffle_by_genre_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Create a list of all tracks in the playlist with a genre-aware and artist-aware shuffle
+    /// using the given random number generator
+    pub fn shuffle_by_genre_with_rng<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Shuffler<Shuffler<&PathBuf>> {
+        let mut genres: BTreeMap<String, BTreeMap<&str, BTreeMap<&PathBuf, usize>>> =
+            BTreeMap::new();
+        for (artist, counter) in self.artists.iter() {
+            for (p, n) in counter.iter() {
+                let genre = self
+                    .tracks
+                    .get(p)
+                    .and_then(|t| t.genre.as_deref())
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase();
+                *genres
+                    .entry(genre)
+                    .or_default()
+                    .entry(artist)
+                    .or_default()
+                    .entry(p)
+                    .or_default() += *n;
+            }
+        }
+        let mut ts = shuffle::Shuffler::new();
+        for (_, artists) in genres {
+            let mut ts2 = shuffle::Shuffler::new();
+            for (_, songs) in artists {
+                let mut ts3 = shuffle::Shuffler::new();
+                for (p, n) in songs {
+                    ts3.addn(p, n);
+                }
+                ts2.nested_add(ts3);
+            }
+            ts2.nested_shuffle_with_rng(rng, self.lookahead);
+            // Keep the artist-aware order within the genre
+            let mut ts3 = shuffle::Shuffler::new();
+            for p in ts2.nested_iter() {
+                ts3.add(*p);
+            }
+            ts.nested_add(ts3);
+        }
+        ts.shuffle_with_rng(rng, self.lookahead);
+        ts
+    }
+
+    /// Create a list of all albums in the playlist, with the albums by the same artist spread apart.
+    /// The tracks within an album are sorted by track number (tracks without an album are treated
+    /// as single-track albums, and ratings are ignored).
+    pub fn shuffle_albums(&self) -> Shuffler<Shuffler<Vec<&PathBuf>>> {
+        self.shuffle_albums_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Create a list of all albums in the playlist using the given random number generator
+    pub fn shuffle_albums_with_rng<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Shuffler<Shuffler<Vec<&PathBuf>>> {
+        let mut artists: BTreeMap<&str, BTreeMap<String, Vec<&PathBuf>>> = BTreeMap::new();
+        for (artist, counter) in self.artists.iter() {
+            for (p, _) in counter.iter() {
+                let album = match self.track(p).and_then(|t| t.album.as_deref()) {
+                    Some(album) => tags::normalize_artist(album),
+                    None => p.to_string_lossy().to_string(),
+                };
+                artists
+                    .entry(artist)
+                    .or_default()
+                    .entry(album)
+                    .or_default()
+                    .push(p);
+            }
+        }
+        let mut ts = shuffle::Shuffler::new();
+        for (_, albums) in artists {
+            let mut ts2 = shuffle::Shuffler::new();
+            for (_, mut tracks) in albums {
+                tracks.sort_by_key(|p| (self.track(p).and_then(|t| t.track_number), *p));
+                ts2.add(tracks);
+            }
+            ts.nested_add(ts2);
+        }
+        ts.nested_shuffle_with_rng(rng, self.lookahead);
+        ts
+    }
+
+    /// Create a list of all tracks in the playlist where the top-level folders are spread apart.
+    /// The folders are relative to the first of the `roots` that contains the track
+    /// (tracks outside the roots are grouped by their parent directory).
+    pub fn shuffle_by_folder(&self, roots: &[PathBuf]) -> Shuffler<Shuffler<&PathBuf>> {
+        self.shuffle_by_folder_with_rng(roots, &mut rand::thread_rng())
+    }
+
+    /// Create a list of all tracks in the playlist where the top-level folders are spread apart
+    /// using the given random number generator
+    pub fn shuffle_by_folder_with_rng<R: Rng + ?Sized>(
+        &self,
+        roots: &[PathBuf],
+        rng: &mut R,
+    ) -> Shuffler<Shuffler<&PathBuf>> {
+        let mut folders: BTreeMap<PathBuf, BTreeMap<&PathBuf, usize>> = BTreeMap::new();
+        for counter in self.artists.values() {
+            for (p, n) in counter.iter() {
+                let folder = roots
+                    .iter()
+                    .find_map(|root| {
+                        let relative = p.strip_prefix(root).ok()?;
+                        // Files directly in the root are grouped together
+                        match relative.parent()?.components().next() {
+                            Some(first) => Some(PathBuf::from(first.as_os_str())),
+                            None => Some(PathBuf::new()),
+                        }
+                    })
+                    .unwrap_or_else(|| p.parent().map(Path::to_path_buf).unwrap_or_default());
+                let weight = folders.entry(folder).or_default().entry(p).or_default();
+                *weight = std::cmp::max(*weight, *n);
+            }
+        }
+        let mut ts = shuffle::Shuffler::new();
+        for (_, tracks) in folders {
+            let mut ts2 = shuffle::Shuffler::new();
+            for (p, n) in tracks {
+                ts2.addn(p, n);
+            }
+            ts.nested_add(ts2);
+        }
+        ts.nested_shuffle_with_rng(rng, self.lookahead);
+        ts
+    }
+
+    // The HashMaps have a random iteration order, so sort to make seeded shuffles reproducible
+    fn shufflers(&self) -> Vec<(&str, Shuffler<&PathBuf>)> {
+        let mut artists: Vec<_> = self.artists.iter().collect();
+        artists.sort_unstable_by_key(|(a, _)| *a);
+        artists
+            .into_iter()
+            .map(|(artist, counter)| {
+                let mut songs: Vec<_> = counter.iter().collect();
+                songs.sort_unstable();
+                let mut ts2 = shuffle::Shuffler::new();
+                for (p, n) in songs {
+                    ts2.addn(p, *n);
+                }
+                (artist.as_str(), ts2)
+            })
+            .collect()
+    }
+}
+
+/// The key used to group tracks by artist
+fn artist_key(artist: &str) -> String {
+    tags::normalize_artist(artist)
+}
+
+/// Check if the file has one of the (lowercase) extensions (any file if there are none)
+fn has_extension(file: &Path, extensions: &[String]) -> bool {
+    extensions.is_empty()
+        || file
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|e| extensions.contains(&e))
+}
+
+/// Split a reader into lines of raw bytes (without the line endings), since paths need not be UTF-8
+fn raw_lines<R: BufRead>(reader: R) -> impl Iterator<Item = Vec<u8>> {
+    reader.split(b'\n').map_while(Result::ok).map(|mut line| {
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        line
+    })
+}
+
+/// Convert a line from a playlist to a path (non-UTF-8 lines are only supported on Unix)
+fn bytes_to_path(bytes: Vec<u8>) -> Option<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(not(unix))]
+    match String::from_utf8(bytes) {
+        Ok(line) => Some(PathBuf::from(line)),
+        Err(e) => {
+            eprintln!(
+                "Skipping a path that is not valid UTF-8: {}",
+                String::from_utf8_lossy(e.as_bytes())
+            );
+            None
+        }
+    }
+}
+
+/// Parse the duration and title from an `#EXTINF:seconds,Artist - Title` line
+fn parse_extinf(info: &str) -> (Option<Duration>, Option<String>) {
+    let (seconds, name) = info.split_once(',').unwrap_or((info, ""));
+    let duration = seconds
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|s| *s >= 0)
+        .map(|s| Duration::from_secs(s as u64));
+    let title = name
+        .split_once(" - ")
+        .map_or(name, |(_, title)| title)
+        .trim();
+    (duration, Some(title.to_string()).filter(|t| !t.is_empty()))
+}
+
+/// How many times a track occurs in the shuffle (a rating of "200" is "4/5").
+/// With a `freshness` the weight is multiplied by up to `freshness + 1`, losing one step
+/// every time the play count doubles (tracks without a play count are unplayed).
+fn track_weight(info: &TrackInfo, freshness: u32) -> usize {
+    let rating = info.rating.map(|r| r / 200 + 1).unwrap_or(1) as usize;
+    let played = (info.play_count.unwrap_or(0) as u64 + 1).ilog2();
+    rating * (1 + freshness.saturating_sub(played) as usize)
+}
+
+/// Read the tags of the files in parallel (the order is preserved)
+#[cfg(feature = "parallel")]
+fn read_tags(files: &[PathBuf], config: &TagConfig) -> Vec<TrackInfo> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = std::cmp::max(1, files.len().div_ceil(threads));
+    std::thread::scope(|s| {
+        let handles: Vec<_> = files
+            .chunks(chunk)
+            .map(|files| {
+                s.spawn(|| {
+                    files
+                        .iter()
+                        .map(|f| tags::get_tags_with_config(f, config))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("reading tags panicked"))
+            .collect()
+    })
+}
+
+/// Read the tags of the files
+#[cfg(not(feature = "parallel"))]
+fn read_tags(files: &[PathBuf], config: &TagConfig) -> Vec<TrackInfo> {
+    files
+        .iter()
+        .map(|f| tags::get_tags_with_config(f, config))
+        .collect()
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (artist, count) in self.artists.iter() {
+            writeln!(f, "{:>6}  {}", count, artist)?;
+        }
+        writeln!(f, "Tracks:  {}", self.total)?;
+        writeln!(f, "Artists: {}", self.artists.len())?;
+        writeln!(f, "Longest run by the same artist: {}", self.max_run)?;
+        match self.min_gap {
+            Some(gap) => writeln!(f, "Smallest gap between the same artist: {}", gap),
+            None => writeln!(f, "Smallest gap between the same artist: -"),
+        }
+    }
+}
+
+/// Split the items into `parts` consecutive parts with (almost) the same length
+pub fn partition<T>(items: Vec<T>, parts: usize) -> Vec<Vec<T>> {
+    let parts = parts.max(1);
+    let (size, extra) = (items.len() / parts, items.len() % parts);
+    let mut items = items.into_iter();
+    (0..parts)
+        .map(|i| items.by_ref().take(size + (i < extra) as usize).collect())
+        .collect()
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_add() {
+        let mut pl = Playlist::new();
+        pl.add_file(PathBuf::from("a/b"));
+        pl.add_file2(&PathBuf::from("d/e/f"), PathBuf::from("d"));
+        pl.add_dir(PathBuf::from("src"));
+        assert!(pl.artists.contains_key(&String::from("a")));
+        assert!(pl.artists.contains_key(&String::from("d")));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a/b"), String::from("a"), None);
+        let copy = pl.clone();
+        pl.add(PathBuf::from("g/h"), String::from("g"), None);
+        assert_eq!(copy.len() + 1, pl.len());
+        assert!(!copy.artists.contains_key(&String::from("g")));
+        assert_eq!(copy.track(Path::new("a/b")), pl.track(Path::new("a/b")));
+    }
+
+    #[test]
+    fn test_artists() {
+        let mut pl = Playlist::new();
+        assert_eq!(pl.artists().count(), 0);
+        pl.add(PathBuf::from("a/1"), String::from("A"), None);
+        pl.add(PathBuf::from("a/2"), String::from("A"), None);
+        pl.add(PathBuf::from("g/h"), String::from("g"), None);
+        let mut artists: Vec<_> = pl.artists().collect();
+        artists.sort_unstable();
+        assert_eq!(artists, ["a", "g"]);
+        assert_eq!(artists.len(), pl.artist_count());
+    }
+
+    #[test]
+    fn test_songs() {
+        let mut pl = Playlist::new();
+        assert_eq!(pl.songs().count(), 0);
+        pl.add(PathBuf::from("x"), String::from("A"), Some(250));
+        pl.add(PathBuf::from("y"), String::from("B"), None);
+        let mut songs: Vec<_> = pl.songs().collect();
+        songs.sort();
+        assert_eq!(
+            songs,
+            [
+                &PathBuf::from("x"),
+                &PathBuf::from("x"),
+                &PathBuf::from("y")
+            ]
+        );
+        assert_eq!(pl.songs().count(), pl.len());
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a/b"), String::from("a"), None);
+        let debug = format!("{:?}", pl);
+        assert!(debug.starts_with("Playlist {"));
+        assert!(debug.contains("a/b"));
+    }
+
+    #[test]
+    fn test_compilation() {
+        let mut pl = Playlist::new();
+        for (i, artist) in ["A", "B", "C"].iter().enumerate() {
+            let info = TrackInfo {
+                artist: String::from(*artist),
+                album: Some(String::from("Now 42")),
+                album_artist: Some(String::from("Various Artists")),
+                ..Default::default()
+            };
+            pl.add_track(PathBuf::from(format!("{}", i)), info);
+        }
+        let info = TrackInfo {
+            artist: String::from("A"),
+            album_artist: Some(String::from("A")),
+            ..Default::default()
+        };
+        pl.add_track(PathBuf::from("3"), info);
+        assert_eq!(pl.get_counter("now 42").unwrap().total(), 3);
+        assert_eq!(pl.get_counter("a").unwrap().total(), 1);
+        assert_eq!(pl.artist_count(), 2);
+    }
+
+    #[test]
+    fn test_file_count() {
+        let mut pl = Playlist::new();
+        pl.set_config(TagConfig {
+            all_artists: true,
+            ..Default::default()
+        });
+        pl.add(PathBuf::from("a1"), String::from("a & b"), Some(255));
+        pl.add(PathBuf::from("b1"), String::from("b"), None);
+        assert_eq!(pl.file_count(), 2);
+        assert_eq!(pl.len(), 5);
+    }
+
+    #[test]
+    fn test_guessed_files() {
+        let mut pl = Playlist::new();
+        pl.add_file(PathBuf::from("Band/Album/01.mp3"));
+        pl.add(PathBuf::from("tagged.mp3"), String::from("a"), None);
+        assert_eq!(pl.guessed_files(), [&PathBuf::from("Band/Album/01.mp3")]);
+        assert_eq!(pl.file_count(), 2);
+    }
+
+    #[test]
+    fn test_filter_by_min_songs() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a1"), String::from("a"), None);
+        pl.add(PathBuf::from("b1"), String::from("b"), None);
+        pl.add(PathBuf::from("b2"), String::from("b"), None);
+        pl.add(PathBuf::from("c1"), String::from("c"), Some(255));
+        pl.add(PathBuf::from("d1"), String::from("d"), None);
+        pl.add(PathBuf::from("d2"), String::from("d"), None);
+        pl.add(PathBuf::from("d3"), String::from("d"), None);
+        pl.filter_by_min_songs(2);
+        let mut artists: Vec<_> = pl.artists().collect();
+        artists.sort_unstable();
+        assert_eq!(artists, ["b", "c", "d"]);
+        pl.filter_by_min_songs(3);
+        assert_eq!(pl.artists().collect::<Vec<_>>(), ["d"]);
+    }
+
+    #[test]
+    fn test_remove_artist() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a1"), String::from("A"), Some(255));
+        pl.add(PathBuf::from("b1"), String::from("b"), None);
+        pl.add(PathBuf::from("b2"), String::from("b"), None);
+        assert!(pl.remove_artist("unknown").is_none());
+        assert_eq!(pl.len(), 4);
+        assert_eq!(pl.artist_count(), 2);
+        let removed = pl.remove_artist("a").unwrap();
+        assert_eq!(removed.total(), 2);
+        assert!(pl.artists().all(|a| a == "b"));
+        assert_eq!(pl.len(), 2);
+        let shuff: Vec<_> = pl.shuffle().nested_iter().copied().collect();
+        assert_eq!(shuff.len(), 2);
+        assert!(!shuff.contains(&&PathBuf::from("a1")));
+        assert!(pl.remove_artist("a").is_none());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = Playlist::new();
+        a.add(PathBuf::from("a1"), String::from("a"), None);
+        a.add(PathBuf::from("b1"), String::from("b"), Some(255));
+        let mut b = Playlist::new();
+        b.add(PathBuf::from("b2"), String::from("B"), None);
+        b.add(PathBuf::from("c1"), String::from("c"), None);
+        b.add(PathBuf::from("c2"), String::from("c"), None);
+        a.merge(b);
+        assert_eq!(a.artist_count(), 3);
+        assert_eq!(a.len(), 6);
+        assert_eq!(a.get_counter("b").unwrap().total(), 3);
+        assert_eq!(a.songs().count(), 6);
+        assert!(a.track(Path::new("c2")).is_some());
+    }
+
+    #[test]
+    fn test_freshness() {
+        let played = |rating, play_count| TrackInfo {
+            artist: String::from("a"),
+            rating,
+            play_count,
+            ..Default::default()
+        };
+        let mut pl = Playlist::new();
+        pl.add_track(PathBuf::from("new"), played(Some(200), Some(0)));
+        pl.add_track(PathBuf::from("old"), played(Some(200), Some(100)));
+        let counter = pl.get_counter("a").unwrap();
+        assert_eq!(counter.count(&PathBuf::from("new")), 2);
+        assert_eq!(counter.count(&PathBuf::from("old")), 2);
+        let mut pl = Playlist::new();
+        pl.set_freshness(2);
+        for (file, rating, count) in [
+            ("new", Some(200), Some(0)),
+            ("unknown", Some(200), None),
+            ("once", Some(200), Some(1)),
+            ("few", Some(200), Some(3)),
+            ("old", Some(200), Some(100)),
+            ("unrated", None, Some(0)),
+        ] {
+            pl.add_track(PathBuf::from(file), played(rating, count));
+        }
+        let counter = pl.get_counter("a").unwrap();
+        let count = |file: &str| counter.count(&PathBuf::from(file));
+        assert_eq!(count("new"), 6);
+        assert_eq!(count("unknown"), 6);
+        assert_eq!(count("once"), 4);
+        assert_eq!(count("few"), 2);
+        assert_eq!(count("old"), 2);
+        assert_eq!(count("unrated"), 3);
+        assert!(count("new") > count("old"));
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let mut pl = Playlist::new();
+        for _ in 0..2 {
+            pl.add(PathBuf::from("a1"), String::from("a"), None);
+            pl.add(PathBuf::from("a2"), String::from("a"), Some(255));
+            pl.add(PathBuf::from("b1"), String::from("b"), None);
+        }
+        pl.add(PathBuf::from("a1"), String::from("c"), None);
+        assert_eq!(pl.len(), 9);
+        pl.deduplicate();
+        assert_eq!(pl.len(), 4);
+        assert_eq!(pl.get_counter("a").unwrap().count(&PathBuf::from("a1")), 1);
+        assert_eq!(pl.get_counter("a").unwrap().count(&PathBuf::from("a2")), 2);
+        assert!(pl.get_counter("c").is_none());
+        // The highest weight is kept, regardless of the order
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a1"), String::from("a"), Some(255));
+        pl.add(PathBuf::from("a1"), String::from("a"), None);
+        pl.add(PathBuf::from("a2"), String::from("a"), None);
+        pl.add(PathBuf::from("a2"), String::from("a"), Some(255));
+        pl.deduplicate();
+        assert_eq!(pl.get_counter("a").unwrap().count(&PathBuf::from("a1")), 2);
+        assert_eq!(pl.get_counter("a").unwrap().count(&PathBuf::from("a2")), 2);
+        // Tracks of multiple artists are kept when all artists are used
+        let mut pl = Playlist::new();
+        pl.set_config(TagConfig {
+            all_artists: true,
+            ..Default::default()
+        });
+        pl.add(PathBuf::from("ab"), String::from("a & b"), None);
+        pl.add(PathBuf::from("ab"), String::from("a & b"), None);
+        pl.deduplicate();
+        assert_eq!(pl.len(), 2);
+        assert_eq!(pl.artist_count(), 2);
+    }
+
+    #[test]
+    fn test_filter_by_rating() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a1"), String::from("a"), Some(255));
+        pl.add(PathBuf::from("a2"), String::from("a"), Some(153));
+        pl.add(PathBuf::from("b1"), String::from("b"), Some(204));
+        pl.add(PathBuf::from("b2"), String::from("b"), None);
+        pl.add(PathBuf::from("c1"), String::from("c"), Some(51));
+        pl.filter_by_rating(3);
+        assert_eq!(pl.artist_count(), 2);
+        assert_eq!(pl.len(), 5);
+        pl.filter_by_rating(4);
+        assert!(!pl.get_counter("a").unwrap().contains(&PathBuf::from("a2")));
+        assert!(pl.get_counter("b").unwrap().contains(&PathBuf::from("b1")));
+        pl.filter_by_rating(5);
+        assert_eq!(pl.songs().collect::<Vec<_>>(), [&PathBuf::from("a1"); 2]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a1"), String::from("A"), Some(255));
+        pl.add(PathBuf::from("a2"), String::from("A"), None);
+        pl.add(PathBuf::from("b1"), String::from("B"), None);
+        pl.add(PathBuf::from("b2"), String::from("B"), None);
+        pl.add(PathBuf::from("c1"), String::from("C"), None);
+        let p = |s: &str| PathBuf::from(s);
+        let (a1, a2, b1, b2, c1) = (p("a1"), p("a2"), p("b1"), p("b2"), p("c1"));
+        let stats = pl.stats(&[&a1, &b1, &a2, &a1, &c1, &b2, &p("x")]);
+        assert_eq!(
+            stats.artists,
+            [
+                (String::from("a"), 3),
+                (String::from("b"), 2),
+                (String::from("c"), 1)
+            ]
+        );
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.max_run, 2);
+        assert_eq!(stats.min_gap, Some(1));
+        let stats = pl.stats(&[&a1, &b1, &a2, &c1, &a1, &b2]);
+        assert_eq!(stats.max_run, 1);
+        assert_eq!(stats.min_gap, Some(2));
+        assert_eq!(pl.stats(&[&a1, &b1]).min_gap, None);
+        let shuffled = pl.shuffle();
+        let tracks: Vec<_> = shuffled.nested_iter().copied().collect();
+        let stats = pl.stats(&tracks);
+        assert_eq!(tracks.len(), stats.total);
+        assert!(stats.to_string().contains("Tracks:  6"));
+    }
+
+    #[test]
+    fn test_read_pls() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_read_pls");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("list.pls");
+        std::fs::write(
+            &file,
+            "[playlist]\nFile1=a/b/c.mp3\nTitle1=C\nLength1=12\nfile2 = d/e/f.mp3\nNumberOfEntries=2\nVersion=2\n",
+        )
+        .unwrap();
+        let mut pl = Playlist::new();
+        pl.read_path(file);
+        let mut songs: Vec<_> = pl.tracks.keys().cloned().collect();
+        songs.sort();
+        assert_eq!(
+            songs,
+            vec![PathBuf::from("a/b/c.mp3"), PathBuf::from("d/e/f.mp3")]
+        );
+        assert!(pl.artists.contains_key("a"));
+        assert!(pl.artists.contains_key("d"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_m3u() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_read_m3u");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("list.m3u8");
+        std::fs::write(
+            &file,
+            "#EXTM3U\n#EXTINF:123,Band - Song\na/b/c.mp3\n\n# comment\n#EXTINF:-1,Other\nd/e/f.mp3\ng/h/i.mp3\n",
+        )
+        .unwrap();
+        let mut pl = Playlist::new();
+        pl.read_path(file);
+        std::fs::remove_dir_all(dir).unwrap();
+        let mut songs: Vec<_> = pl.tracks.keys().cloned().collect();
+        songs.sort();
+        assert_eq!(
+            songs,
+            ["a/b/c.mp3", "d/e/f.mp3", "g/h/i.mp3"].map(PathBuf::from)
+        );
+        let info = pl.track(Path::new("a/b/c.mp3")).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Song"));
+        assert_eq!(info.duration, Some(Duration::from_secs(123)));
+        let info = pl.track(Path::new("d/e/f.mp3")).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Other"));
+        assert_eq!(info.duration, None);
+        assert_eq!(pl.track(Path::new("g/h/i.mp3")).unwrap().title, None);
+        assert_eq!(pl.artist_count(), 3);
+    }
+
+    #[test]
+    fn test_read_from_reader() {
+        let mut pl = Playlist::new();
+        let list = std::io::Cursor::new(&b"a/b/c.mp3\n\n# comment\nd/e/f.mp3\n"[..]);
+        pl.read_from_reader(list, None);
+        let mut songs: Vec<_> = pl.tracks.keys().cloned().collect();
+        songs.sort();
+        assert_eq!(songs, ["a/b/c.mp3", "d/e/f.mp3"].map(PathBuf::from));
+        assert!(pl.artists.contains_key("a"));
+        // Relative paths are resolved from the base directory, but keep their form
+        let mut pl = Playlist::new();
+        let list = std::io::Cursor::new(&b"Band - Song.mp3\n"[..]);
+        pl.read_from_reader(list, Some(Path::new("Other")));
+        assert_eq!(
+            pl.songs().collect::<Vec<_>>(),
+            [&PathBuf::from("Band - Song.mp3")]
+        );
+        assert!(pl.artists.contains_key("band"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut pl = Playlist::new();
+        // "café" in Latin-1
+        pl.read_from_reader(&b"a/caf\xe9.mp3\r\nb/c.mp3\n"[..], None);
+        let mut songs: Vec<_> = pl.tracks.keys().map(|p| p.as_os_str().as_bytes()).collect();
+        songs.sort();
+        assert_eq!(songs, [&b"a/caf\xe9.mp3"[..], b"b/c.mp3"]);
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_read_csv");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("list.csv");
+        std::fs::write(
+            &file,
+            "Rating,Path,Artist\n255,a/b/c.mp3,Someone\n,\"d/e,f/g.mp3\",\n",
+        )
+        .unwrap();
+        let mut pl = Playlist::new();
+        pl.read_path(file.clone());
+        assert_eq!(
+            pl.artists["someone"].iter().next(),
+            Some((&PathBuf::from("a/b/c.mp3"), &2))
+        );
+        assert_eq!(pl.track(Path::new("a/b/c.mp3")).unwrap().rating, Some(255));
+        assert!(pl.artists.contains_key("d"));
+        assert_eq!(pl.len(), 3);
+
+        std::fs::write(&file, "a/b/c.mp3\nd/e/f.mp3,x\n").unwrap();
+        let mut pl = Playlist::new();
+        pl.read_path(file);
+        assert!(pl.artists.contains_key("a"));
+        assert!(pl.artists.contains_key("d"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv_weights() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a/1.mp3"), String::from("Band"), Some(255));
+        pl.add(PathBuf::from("a/2.mp3"), String::from("Other"), None);
+        let info = TrackInfo {
+            artist: String::from("Crosby, Stills"),
+            ..Default::default()
+        };
+        pl.add_track_weighted(PathBuf::from("b,c/3.mp3"), info.clone(), 5);
+        pl.add_track_weighted(PathBuf::from("b,c/4.mp3"), info, 0);
+        assert_eq!(pl.file_count(), 3);
+        let mut csv = Vec::new();
+        pl.write_csv(&mut csv, false).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&csv).unwrap(),
+            "a/1.mp3,Band,255,2\n\"b,c/3.mp3\",\"Crosby, Stills\",,5\na/2.mp3,Other,,1\n"
+        );
+        // Edit the weights and read them back (which needs the header)
+        let mut csv = Vec::new();
+        pl.write_csv(&mut csv, true).unwrap();
+        assert!(csv.starts_with(b"path,artist,rating,weight\n"));
+        let edited = std::str::from_utf8(&csv)
+            .unwrap()
+            .replace("Band,255,2", "Band,255,3");
+        let mut pl2 = Playlist::new();
+        pl2.read_csv_from_reader(edited.as_bytes(), None, "path");
+        assert_eq!(pl2.get_counter("Band").unwrap().total(), 3);
+        assert_eq!(pl2.get_counter("Crosby, Stills").unwrap().total(), 5);
+        assert_eq!(pl2.get_counter("Other").unwrap().total(), 1);
+        assert_eq!(pl2.artist_count(), pl.artist_count());
+        assert_eq!(pl2.track(Path::new("a/1.mp3")).unwrap().rating, Some(255));
+        // Without a header the other columns (e.g. a rating) are not weights
+        let mut pl3 = Playlist::new();
+        pl3.read_csv_from_reader(&b"x/a.mp3,Someone,255\n"[..], None, "path");
+        assert_eq!(pl3.len(), 1);
+        assert!(pl3.get_counter("Someone").is_none());
+    }
+
+    #[test]
+    fn test_csv_output_round_trip() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a/1.mp3"), String::from("Band"), Some(255));
+        pl.add(PathBuf::from("a/2.mp3"), String::from("Band"), None);
+        pl.add(PathBuf::from("b/3.mp3"), String::from("Other"), None);
+        let info = TrackInfo {
+            artist: String::from("Other"),
+            ..Default::default()
+        };
+        pl.add_track_weighted(PathBuf::from("c/4.mp3"), info, 4);
+        let tracks: Vec<_> = pl.shuffle().nested_iter().copied().collect();
+        let mut csv = Vec::new();
+        let entries = tracks.iter().map(|p| (*p, pl.track(p)));
+        formats::write(formats::Format::Csv, &mut csv, entries).unwrap();
+        // Every track is listed in the shuffled order, with the same header as a playlist CSV
+        let records = formats::csv_records(std::str::from_utf8(&csv).unwrap());
+        let listed: Vec<_> = records[1..].iter().map(|r| PathBuf::from(&r[0])).collect();
+        assert!(listed.iter().eq(tracks.iter().copied()));
+        let mut header = Vec::new();
+        pl.write_csv(&mut header, true).unwrap();
+        assert!(header.starts_with(format!("{}\n", records[0].join(",")).as_bytes()));
+        let mut pl2 = Playlist::new();
+        pl2.read_csv_from_reader(&csv[..], None, "path");
+        assert_eq!(pl2.len(), pl.len());
+        for (artist, counter) in pl.artists.iter() {
+            let counter2 = &pl2.artists[artist];
+            for (path, weight) in counter.iter() {
+                assert_eq!(counter2.count(path), *weight);
+                assert_eq!(
+                    pl2.track(path).unwrap().rating,
+                    pl.track(path).unwrap().rating
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_xspf_round_trip() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a/b & c/d.mp3"), String::from("a"), None);
+        pl.add(PathBuf::from("/e/f/ü.mp3"), String::from("e"), Some(255));
+        let file = std::env::temp_dir().join("artistic_shuffle_test_round_trip.xspf");
+        let shuffled = pl.shuffle();
+        let entries = shuffled.nested_iter().map(|p| (*p, pl.track(p)));
+        formats::write(
+            formats::Format::from_path(&file),
+            &mut File::create(&file).unwrap(),
+            entries,
+        )
+        .unwrap();
+        let mut pl2 = Playlist::new();
+        pl2.read_path(file.clone());
+        std::fs::remove_file(file).unwrap();
+        let mut songs: Vec<_> = pl2.tracks.into_keys().collect();
+        songs.sort();
+        assert_eq!(
+            songs,
+            vec![PathBuf::from("/e/f/ü.mp3"), PathBuf::from("a/b & c/d.mp3")]
+        );
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("a"), String::from("a"), None);
+        pl.add(PathBuf::from("b"), String::from("a"), Some(199));
+        pl.add(PathBuf::from("c"), String::from("b"), Some(200));
+        pl.add(PathBuf::from("d"), String::from("b"), Some(201));
+
+        let shuff = pl.shuffle().nested_iter().copied().collect::<Vec<_>>();
+
+        assert!(shuff.contains(&&PathBuf::from("a")));
+        assert!(shuff.contains(&&PathBuf::from("b")));
+        assert!(shuff.contains(&&PathBuf::from("c")));
+        assert!(shuff.contains(&&PathBuf::from("d")));
+        assert_eq!(shuff.len(), 6);
+    }
+
+    #[test]
+    fn test_len() {
+        let mut pl = Playlist::new();
+        assert!(pl.is_empty());
+        assert_eq!(pl.len(), 0);
+        assert_eq!(pl.artist_count(), 0);
+        pl.add(PathBuf::from("a"), String::from("a"), None);
+        pl.add(PathBuf::from("b"), String::from("a"), Some(199));
+        pl.add(PathBuf::from("c"), String::from("b"), Some(200));
+        pl.add(PathBuf::from("d"), String::from("b"), Some(201));
+        assert!(!pl.is_empty());
+        assert_eq!(pl.len(), 6);
+        assert_eq!(pl.artist_count(), 2);
+    }
+
+    #[test]
+    fn test_shuffle_seeded() {
+        let mut pl = Playlist::new();
+        for i in 0..12 {
+            pl.add(PathBuf::from(i.to_string()), (i % 4).to_string(), None);
+        }
+        let seeded = |seed| {
+            pl.shuffle_seeded(seed)
+                .nested_iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seeded(1), seeded(1));
+        assert_eq!(seeded(1).len(), 12);
+        assert!((2..10).any(|seed| seeded(seed) != seeded(1)));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut pl = Playlist::new();
+        for i in 0..12 {
+            pl.add(PathBuf::from(i.to_string()), (i % 4).to_string(), None);
+        }
+        let shuffled = pl.shuffle();
+        let limited: Vec<_> = shuffled.nested_iter().take(5).collect();
+        assert_eq!(limited.len(), 5);
+        assert!(limited.iter().zip(limited.iter().skip(1)).all(|(a, b)| pl
+            .track(a)
+            .unwrap()
+            .artist
+            != pl.track(b).unwrap().artist));
+        assert_eq!(shuffled.nested_iter().take(20).count(), 12);
+    }
+
+    #[test]
+    fn test_partition() {
+        let mut pl = Playlist::new();
+        for i in 0..11 {
+            pl.add(PathBuf::from(i.to_string()), (i % 3).to_string(), None);
+        }
+        let shuffled = pl.shuffle();
+        let parts = partition(shuffled.nested_iter().copied().collect(), 3);
+        assert_eq!(parts.iter().map(|p| p.len()).collect::<Vec<_>>(), [4, 4, 3]);
+        let mut songs: Vec<_> = parts.into_iter().flatten().collect();
+        songs.sort();
+        songs.dedup();
+        let mut expected: Vec<_> = pl.tracks.keys().collect();
+        expected.sort();
+        assert_eq!(songs, expected);
+        assert_eq!(partition(vec![1], 3), [vec![1], vec![], vec![]]);
+    }
+
+    #[test]
+    fn test_add_dir_parallel() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_add_dir_parallel");
+        for artist in 0..5 {
+            let album = dir.join(format!("artist{}", artist)).join("album");
+            std::fs::create_dir_all(&album).unwrap();
+            for track in 0..20 {
+                std::fs::write(album.join(format!("{}.mp3", track)), b"").unwrap();
+            }
+        }
+        std::fs::write(dir.join(".hidden.mp3"), b"").unwrap();
+        let mut parallel = Playlist::new();
+        parallel.add_dir(dir.clone());
+        let mut sequential = Playlist::new();
+        for entry in WalkDir::new(&dir) {
+            let path = entry.unwrap().into_path();
+            if path.is_file() && !path.ends_with(".hidden.mp3") {
+                sequential.add_file(path);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parallel.tracks, sequential.tracks);
+        assert_eq!(parallel.len(), sequential.len());
+        assert_eq!(parallel.artist_count(), sequential.artist_count());
+        assert!(parallel.artists.contains_key("artist3"));
+    }
+
+    #[test]
+    fn test_ignore() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_ignore");
+        for album in [
+            "Band/Album",
+            "Podcasts/Show",
+            "Audiobooks/Book",
+            "Other/Live",
+        ] {
+            std::fs::create_dir_all(dir.join(album)).unwrap();
+            std::fs::write(dir.join(album).join("01.mp3"), b"").unwrap();
+        }
+        std::fs::write(dir.join(".shuffleignore"), b"# comment\nAudiobooks\n").unwrap();
+        let mut ignore = Ignore::new();
+        ignore.add("Podcasts");
+        ignore.add("Other/**/*.mp3");
+        let mut pl = Playlist::new();
+        pl.set_ignore(ignore);
+        pl.add_dir(dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut songs: Vec<_> = pl
+            .songs()
+            .filter_map(|p| p.strip_prefix(&dir).ok())
+            .filter(|p| p.extension().is_some())
+            .collect();
+        songs.sort_unstable();
+        assert_eq!(songs, [Path::new("Band/Album/01.mp3")]);
+        assert!(!pl
+            .tracks
+            .keys()
+            .any(|p| p.starts_with(dir.join("Podcasts"))));
+        assert!(!pl
+            .tracks
+            .keys()
+            .any(|p| p.starts_with(dir.join("Audiobooks"))));
+    }
+
+    #[test]
+    fn test_write_to_writer() {
+        let mut pl = Playlist::new();
+        pl.add(PathBuf::from("/music/a/1.mp3"), String::from("a"), None);
+        pl.add(PathBuf::from("/music/b/1.mp3"), String::from("b"), None);
+        pl.add(PathBuf::from("/other/c/1.mp3"), String::from("c"), None);
+        let mut out = Vec::new();
+        pl.write_to_writer(&mut out, Some(Path::new("/music/a")))
+            .unwrap();
+        let mut lines: Vec<_> = std::str::from_utf8(&out).unwrap().lines().collect();
+        lines.sort_unstable();
+        assert_eq!(lines, ["../../other/c/1.mp3", "../b/1.mp3", "1.mp3"]);
+        let mut out = Vec::new();
+        pl.write_to_writer(&mut out, None).unwrap();
+        assert_eq!(std::str::from_utf8(&out).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_extensions() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_extensions");
+        let album = dir.join("Band").join("Album");
+        std::fs::create_dir_all(&album).unwrap();
+        for file in ["01.mp3", "02.FLAC", "cover.jpg", "info.nfo", "album.cue"] {
+            std::fs::write(album.join(file), b"").unwrap();
+        }
+        let mut pl = Playlist::new();
+        pl.add_dir(dir.clone());
+        let mut songs: Vec<_> = pl.songs().map(|p| p.file_name().unwrap()).collect();
+        songs.sort_unstable();
+        assert_eq!(songs, ["01.mp3", "02.FLAC"]);
+        let mut pl = Playlist::new();
+        pl.set_extensions(vec![String::from(".JPG")]);
+        pl.add_dir(dir.clone());
+        assert_eq!(pl.songs().collect::<Vec<_>>(), [&album.join("cover.jpg")]);
+        let mut pl = Playlist::new();
+        pl.set_extensions(Vec::new());
+        pl.add_dir(dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pl.len(), 5);
+    }
+
+    #[test]
+    fn test_add_dir_with_extensions() {
+        let mut pl = Playlist::new();
+        pl.add_dir_with_extensions(PathBuf::from("src"), &[".RS"]);
+        let files: Vec<_> = pl.songs().collect();
+        assert!(files.contains(&&PathBuf::from("src/lib.rs")));
+        assert!(files.iter().all(|f| f.extension().unwrap() == "rs"));
+        let mut pl = Playlist::new();
+        pl.add_dir(PathBuf::from("src"));
+        assert!(pl.is_empty());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_max_depth");
+        let deep = dir.join("a").join("b");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::write(dir.join("0.mp3"), b"").unwrap();
+        std::fs::write(dir.join("a").join("1.mp3"), b"").unwrap();
+        std::fs::write(deep.join("2.mp3"), b"").unwrap();
+        let mut pl = Playlist::new();
+        pl.add_dir_with_depth(dir.clone(), 1);
+        assert_eq!(pl.file_count(), 1);
+        pl.add_dir_with_depth(dir.clone(), 2);
+        assert_eq!(pl.file_count(), 2);
+        let mut pl = Playlist::new();
+        pl.set_max_depth(Some(2));
+        pl.add_path(dir.clone());
+        assert_eq!(pl.file_count(), 2);
+        pl.set_max_depth(None);
+        pl.add_path(dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(pl.file_count(), 3);
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let mut pl = Playlist::new();
+        for (artist, tracks) in [("a", 12), ("b", 10), ("c", 8), ("d", 6), ("e", 4)] {
+            for i in 0..tracks {
+                pl.add(
+                    PathBuf::from(format!("{}{}", artist, i)),
+                    artist.into(),
+                    None,
+                );
+            }
+        }
+        let violations = |pl: &Playlist| -> usize {
+            (0..20)
+                .map(|seed| pl.shuffle_seeded(seed).adjacency_violations())
+                .sum()
+        };
+        pl.set_lookahead(1);
+        let narrow = violations(&pl);
+        pl.set_lookahead(20);
+        let wide = violations(&pl);
+        assert!(wide < narrow, "{} < {}", wide, narrow);
+        assert_eq!(wide, 0);
+    }
+
+    #[test]
+    fn test_take_duration() {
+        let mut pl = Playlist::new();
+        for (i, seconds) in [60, 120, 0, 90].iter().enumerate() {
+            let info = TrackInfo {
+                duration: Some(Duration::from_secs(*seconds)).filter(|d| !d.is_zero()),
+                ..Default::default()
+            };
+            pl.add_track(PathBuf::from(i.to_string()), info);
+        }
+        let tracks: Vec<_> = (0..4).map(|i| PathBuf::from(i.to_string())).collect();
+        let take = |max: u64| {
+            pl.take_duration(
+                tracks.iter(),
+                Duration::from_secs(max),
+                Duration::from_secs(30),
+            )
+            .count()
+        };
+        assert_eq!(take(59), 0);
+        assert_eq!(take(60), 1);
+        assert_eq!(take(209), 2);
+        assert_eq!(take(210), 3);
+        assert_eq!(take(300), 4);
+    }
+
+    #[test]
+    fn test_shuffle_albums() {
+        let mut pl = Playlist::new();
+        for (artist, album) in [("A", "X"), ("A", "Y"), ("B", "Z"), ("C", "W")] {
+            for track in (1..=3).rev() {
+                let info = TrackInfo {
+                    artist: String::from(artist),
+                    album: Some(String::from(album)),
+                    track_number: Some(track),
+                    rating: Some(255),
+                    ..Default::default()
+                };
+                pl.add_track(PathBuf::from(format!("{}{}", album, track)), info);
+            }
+        }
+        pl.add(PathBuf::from("single"), String::from("D"), None);
+        for _ in 0..10 {
+            let shuffled = pl.shuffle_albums();
+            let albums: Vec<_> = shuffled.nested_iter().collect();
+            assert_eq!(albums.len(), 5);
+            for album in albums.iter().filter(|a| a.len() > 1) {
+                let names: Vec<_> = album.iter().map(|p| p.to_string_lossy()).collect();
+                assert_eq!(names.len(), 3);
+                assert!(names[0].ends_with('1') && names[2].ends_with('3'));
+            }
+            let tracks: Vec<_> = albums.iter().flat_map(|a| a.iter()).collect();
+            assert_eq!(tracks.len(), 13);
+        }
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1);
+        assert_eq!(pl.shuffle_albums_with_rng(&mut rng).len(), 5);
+    }
+
+    #[test]
+    fn test_grouping() {
+        assert_eq!("artist".parse(), Ok(Grouping::Artist));
+        assert_eq!("Genre".parse(), Ok(Grouping::Genre));
+        assert_eq!("folder".parse(), Ok(Grouping::Folder));
+        assert!("mood".parse::<Grouping>().unwrap_err().contains("'mood'"));
+        assert_eq!(Grouping::default(), Grouping::Artist);
+    }
+
+    #[test]
+    fn test_guess_artist() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_guess_artist");
+        std::fs::create_dir_all(dir.join("Artist/Album")).unwrap();
+        std::fs::write(dir.join("Daft Punk - One More Time.mp3"), b"").unwrap();
+        std::fs::write(dir.join("Artist/Album/Song - Live.mp3"), b"").unwrap();
+        let mut pl = Playlist::new();
+        pl.add_dir(dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(dir.is_absolute());
+        assert_eq!(pl.artist_count(), 2);
+        assert!(pl.get_counter("Daft Punk").is_some());
+        assert!(pl.get_counter("Artist").is_some());
+    }
+
+    #[test]
+    fn test_shuffle_by_folder() {
+        let dir = PathBuf::from("music");
+        let mut pl = Playlist::new();
+        for folder in ["A/x", "B/y", "C/z/w", "D"] {
+            for i in 0..4 {
+                let path = dir.join(folder).join(format!("{}.mp3", i));
+                pl.add(path, String::from("Band"), None);
+            }
+        }
+        pl.add(dir.join("loose.mp3"), String::from("Band"), None);
+        pl.add(PathBuf::from("other/E/1.mp3"), String::from("e"), None);
+        // All the folders have the same artist, but the folders are still spread apart
+        assert_eq!(pl.artist_count(), 2);
+        let shuffled = pl
+            .shuffle_by_folder_with_rng(std::slice::from_ref(&dir), &mut StdRng::seed_from_u64(3));
+        assert_eq!(shuffled.len(), 18);
+        let folders: Vec<_> = shuffled
+            .nested_iter()
+            .map(|p| match p.strip_prefix(&dir) {
+                Ok(p) if p.components().count() > 1 => p.components().next().unwrap().as_os_str(),
+                Ok(_) => "root".as_ref(),
+                Err(_) => p.parent().unwrap().as_os_str(),
+            })
+            .collect();
+        for folder in ["A", "B", "C", "D"] {
+            assert_eq!(folders.iter().filter(|f| **f == folder).count(), 4);
+        }
+        assert!(folders.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_shuffle_by_genre() {
+        let mut pl = Playlist::new();
+        for (i, (artist, genre)) in [("a", "x"), ("b", "x"), ("a", "y"), ("b", "y")]
+            .iter()
+            .cycle()
+            .take(8)
+            .enumerate()
+        {
+            let info = TrackInfo {
+                artist: String::from(*artist),
+                genre: Some(String::from(*genre)),
+                ..Default::default()
+            };
+            pl.add_track(PathBuf::from(i.to_string()), info);
+        }
+        for _ in 0..10 {
+            let shuff = pl
+                .shuffle_by_genre()
+                .nested_iter()
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(shuff.len(), 8);
+            assert!(shuff.iter().zip(shuff.iter().skip(1)).all(|(a, b)| {
+                let (a, b) = (&pl.tracks[*a], &pl.tracks[*b]);
+                a.artist != b.artist || a.genre != b.genre
+            }));
+        }
+        let seeded = |seed| {
+            pl.shuffle_by_genre_with_rng(&mut StdRng::seed_from_u64(seed))
+                .nested_iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seeded(3), seeded(3));
+    }
+}
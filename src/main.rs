@@ -1,227 +1,348 @@
-use shuffle::{Counter, Shuffler};
+use artistic_shuffle::formats::{self, Format, PathStyle};
+use artistic_shuffle::shuffle::hash_seed;
+use artistic_shuffle::{Grouping, Ignore, Playlist, TagCache, TagConfig};
+use flags::Artistic_shuffle;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::collections::HashMap;
 use std::fs::{create_dir_all, File};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{Result, Write};
 use std::path::{Path, PathBuf};
-use walkdir::{DirEntry, WalkDir};
+use std::time::Duration;
 
-mod shuffle;
-mod tags;
+mod flags;
 
-pub struct Playlist(HashMap<String, Counter<PathBuf>>);
-
-impl Playlist {
-    pub fn new() -> Playlist {
-        Playlist(HashMap::new())
+fn main() {
+    let flags = match Artistic_shuffle::from_args(std::env::args_os().skip(1).collect()) {
+        Ok(flags) => flags,
+        Err(e) => e.exit(),
+    };
+    if flags.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return;
     }
-
-    // Add a track with known artist and rating
-    pub fn add(&mut self, file: PathBuf, artist: String, rating: Option<u8>) {
-        let artist = artist.trim().to_lowercase();
-        // A rating of "200" is "4/5"
-        let times = rating.map(|r| r / 200 + 1).unwrap_or(1) as usize;
-        match self.0.get_mut(&artist) {
-            Some(counter) => counter.addn(file, times),
-            None => {
-                let mut counter = Counter::new();
-                counter.addn(file, times);
-                self.0.insert(artist, counter);
+    let mut pl = Playlist::new();
+    if let Some(keys) = flags.artist_key_order.as_deref() {
+        match TagConfig::parse_artist_keys(keys) {
+            Ok(artist_keys) => pl.set_config(TagConfig {
+                artist_keys,
+                ..Default::default()
+            }),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
             }
         }
     }
-
-    /// Add the path to the playlist (recursively if it is a directory)
-    pub fn add_path(&mut self, path: PathBuf) {
-        if path.is_dir() {
-            self.add_dir(path)
-        } else {
-            self.add_file(path)
+    if !flags.ignore.is_empty() {
+        let mut ignore = Ignore::new();
+        for pattern in flags.ignore.iter() {
+            ignore.add(pattern);
         }
+        pl.set_ignore(ignore);
     }
-
-    pub fn add_file(&mut self, file: PathBuf) {
-        let (artist, rating) = tags::get_tags(&file);
-        self.add(file, artist, rating);
+    pl.set_max_depth(flags.depth);
+    if let Some(lookahead) = flags.lookahead {
+        pl.set_lookahead(lookahead);
     }
-
-    /// Add a file with a different output path
-    pub fn add_file2(&mut self, file: &Path, path: PathBuf) {
-        let (artist, rating) = tags::get_tags(file);
-        self.add(path, artist, rating);
+    if let Some(freshness) = flags.freshness {
+        pl.set_freshness(freshness);
     }
-
-    fn add_dir(&mut self, path: PathBuf) {
-        let is_not_hidden = |e: &DirEntry| {
-            e.file_name()
-                .to_str()
-                .map(|s| !s.starts_with('.'))
-                .unwrap_or(true)
-        };
-        for entry in WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
-            .filter_entry(is_not_hidden)
-        {
-            match entry {
-                Ok(entry) => self.add_file(entry.path().to_path_buf()),
-                Err(error) => eprintln!("Could not access file: {}", error),
-            }
+    if !flags.ext.is_empty() {
+        pl.set_extensions(flags.ext.clone());
+    }
+    let cache_path = TagCache::default_path().filter(|_| flags.cache);
+    if let Some(path) = cache_path.as_ref() {
+        pl.set_cache(TagCache::load(path));
+    }
+    if flags.path.is_empty() && flags.read.is_empty() {
+        pl.add_path(PathBuf::from("."));
+    }
+    let is_stdin = |path: &PathBuf| path.as_os_str() == "-";
+    for path in flags.path.iter() {
+        if is_stdin(path) {
+            pl.read_from_reader(std::io::stdin().lock(), None);
+        } else {
+            pl.add_path(path.clone());
         }
     }
-
-    /// Read the contents of the file and add to the playlist (recursively if it is a directory)
-    pub fn read_path(&mut self, path: PathBuf) {
-        match path.metadata() {
-            Ok(md) => {
-                if md.is_dir() {
-                    self.read_dir(path)
-                } else if md.is_file() {
-                    self.read_file(&path)
-                } else {
-                    eprintln!("Unknown type of object: {}", path.to_string_lossy())
-                }
-            }
-            Err(e) => eprintln!("Error accessing path '{}': {}", path.to_string_lossy(), e),
+    for path in flags.read.iter() {
+        if is_stdin(path) {
+            pl.read_from_reader(std::io::stdin().lock(), None);
+        } else {
+            pl.read_path(path.clone());
         }
     }
-
-    /// Read and add files from a file (e.g. playlist)
-    fn read_file(&mut self, file: &Path) {
-        let parent = file.parent();
-        if let Ok(f) = File::open(file) {
-            for line in BufReader::new(f).lines().flatten() {
-                let path = PathBuf::from(line);
-                if parent.is_none() || path.is_absolute() {
-                    self.add_file(path);
-                } else {
-                    #[allow(clippy::unnecessary_unwrap)]
-                    self.add_file2(&parent.unwrap().join(&path), path);
-                }
-            }
+    if flags.dedupe {
+        pl.deduplicate();
+    }
+    if flags.verbose {
+        eprintln!(
+            "Found {} files by {} artists",
+            pl.file_count(),
+            pl.artist_count()
+        );
+    }
+    if let Some(min) = flags.min_rating {
+        pl.filter_by_rating(min);
+    }
+    if let Some(min) = flags.min_songs {
+        pl.filter_by_min_songs(min);
+    }
+    if flags.verbose {
+        print_artists(&pl);
+    }
+    print_guessed(&pl, flags.verbose);
+    if let (Some(path), Some(cache)) = (cache_path.as_ref(), pl.cache()) {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Could not write the tag cache '{}': {}", path.display(), e);
         }
     }
-
-    fn read_dir(&mut self, path: PathBuf) {
-        let is_not_hidden = |e: &DirEntry| {
-            e.file_name()
-                .to_str()
-                .map(|s| !s.starts_with('.'))
-                .unwrap_or(true)
+    let format = |path: Option<&PathBuf>| match path.map(|p| Format::from_path(p)) {
+        Some(Format::Plain) | None if flags.extended => Format::M3u,
+        Some(format) => format,
+        None => Format::Plain,
+    };
+    let style = flags.paths.unwrap_or_default();
+    let mut failed = false;
+    let mut write = |path: &PathBuf, tracks: &[&PathBuf]| {
+        let parts: Vec<(PathBuf, &[&PathBuf])> = match flags.split {
+            Some(n) if n > 0 => {
+                let parts = tracks.chunks(n).len();
+                let chunks = tracks.chunks(n).enumerate();
+                chunks
+                    .map(|(i, c)| (numbered_path(path, i + 1, parts), c))
+                    .collect()
+            }
+            _ => vec![(path.clone(), tracks)],
         };
-        for entry in WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
-            .filter_entry(is_not_hidden)
-        {
-            match entry {
-                Ok(entry) => self.read_file(entry.path()),
-                Err(error) => eprintln!("Could not access file: {}", error),
+        for (path, tracks) in parts {
+            match write_file(&pl, tracks, format(Some(&path)), style, &path) {
+                Ok(()) if flags.verbose => {
+                    eprintln!("Wrote {} tracks to '{}'", tracks.len(), path.display())
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    eprintln!("Could not write to output file '{}': {}", path.display(), e);
+                    failed = true;
+                }
             }
         }
-    }
-
-    /// Create a list of all tracks in the playlist with an artist-aware shuffle
-    pub fn shuffle(&self) -> Shuffler<Shuffler<&PathBuf>> {
-        let mut ts = shuffle::Shuffler::new();
-        for (_, counter) in self.0.iter() {
-            let mut ts2 = shuffle::Shuffler::new();
-            for (p, n) in counter.iter() {
-                ts2.addn(p, *n);
-            }
-            ts.nested_add(ts2);
+    };
+    if flags.stats {
+        let tracks = select_tracks(&pl, &flags, 0);
+        print!("{}", pl.stats(&tracks));
+    } else if flags.output.is_empty() {
+        let tracks = select_tracks(&pl, &flags, 0);
+        let mut stdout = std::io::stdout().lock();
+        let cwd = Path::new("");
+        if let Err(e) = write_playlist(&pl, &tracks, format(None), style, cwd, &mut stdout) {
+            eprintln!("Could not write the playlist: {}", e);
+            failed = true;
+        }
+    } else if flags.partition {
+        let tracks = select_tracks(&pl, &flags, 0);
+        let parts = artistic_shuffle::partition(tracks, flags.output.len());
+        for (path, tracks) in flags.output.iter().zip(parts) {
+            write(path, &tracks);
+        }
+    } else {
+        for (i, path) in flags.output.iter().enumerate() {
+            let tracks = select_tracks(&pl, &flags, i as u64);
+            write(path, &tracks);
         }
-        ts.nested_shuffle(10);
-        ts
+    }
+    if failed {
+        std::process::exit(1);
     }
 }
 
-impl Default for Playlist {
-    fn default() -> Self {
-        Self::new()
+/// Print the number of tracks of every artist to STDERR (highly rated tracks count multiple times)
+fn print_artists(pl: &Playlist) {
+    let mut artists: Vec<_> = pl
+        .artists()
+        .map(|a| (pl.get_counter(a).map_or(0, |c| c.total()), a))
+        .collect();
+    artists.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+    eprintln!("{} artists with {} tracks:", artists.len(), pl.len());
+    for (count, artist) in artists {
+        eprintln!("{:>6}  {}", count, artist);
     }
 }
 
-fn main() {
-    xflags::xflags! {
-        /// Create a shuffled playlist where no artist is repeated too often and highly rated tracks occur more often.
-        /// The artists and the ratings (4/5 ★ and up) are taken from the files' metadata.
-        /// If metadata is missing then the artist is based on the path (assuming an 'artist/album/track' directory structure).
-        /// Relative paths are preserved, so make sure that the output is in the correct location.
-        cmd artistic_shuffle {
-            /// Files to add to the playlist (directories are recursively added).
-            repeated path: PathBuf
-            /// Files with lists to add to the playlist (directories are recursively read).
-            repeated -r,--read path: PathBuf
-            /// Files to write the playlist to. The playlist is reshuffled for every output. The playlist is printed to STDOUT if no output is given.
-            repeated -o,--output path: PathBuf
-        }
-    };
-    let flags = match Artistic_shuffle::from_env() {
-        Ok(flags) => flags,
-        Err(e) => e.exit(),
-    };
-    let mut pl = Playlist::new();
-    for path in flags.path {
-        pl.add_path(path);
-    }
-    for path in flags.read {
-        pl.read_path(path);
+/// Print how many artists were guessed from the paths to STDERR
+/// (and the folders with the most guessed artists if `verbose`)
+fn print_guessed(pl: &Playlist, verbose: bool) {
+    let guessed = pl.guessed_files();
+    if guessed.is_empty() {
+        return;
     }
-    if flags.output.is_empty() {
-        for path in pl.shuffle().nested_iter() {
-            println!("{}", path.display());
+    eprintln!(
+        "{} of {} tracks have no artist tag (the artist was guessed from the path)",
+        guessed.len(),
+        pl.file_count()
+    );
+    if verbose {
+        let mut folders: HashMap<&Path, usize> = HashMap::new();
+        for file in guessed {
+            *folders.entry(file.parent().unwrap_or(file)).or_default() += 1;
+        }
+        let mut folders: Vec<_> = folders.into_iter().collect();
+        folders.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (folder, count) in folders.into_iter().take(10) {
+            eprintln!("{:>6}  {}", count, folder.display());
         }
     }
-    for path in flags.output {
-        if let Some(parent) = path.parent() {
-            if let Err(e) = create_dir_all(parent) {
-                eprintln!("Could not create directories '{}': {}", parent.display(), e);
-            }
+}
+
+/// Shuffle the playlist (with the seed of output `index`) and select the tracks to write
+fn select_tracks<'a>(pl: &'a Playlist, flags: &Artistic_shuffle, index: u64) -> Vec<&'a PathBuf> {
+    let seed = flags.seed().map(|seed| output_seed(seed, index));
+    let shuffled: Vec<&PathBuf> = match (flags.group.unwrap_or_default(), seed) {
+        (Grouping::Genre, Some(seed)) => pl
+            .shuffle_by_genre_with_rng(&mut StdRng::seed_from_u64(seed))
+            .nested_iter()
+            .copied()
+            .collect(),
+        (Grouping::Genre, None) => pl.shuffle_by_genre().nested_iter().copied().collect(),
+        (Grouping::Album, seed) => {
+            let albums = match seed {
+                Some(seed) => pl.shuffle_albums_with_rng(&mut StdRng::seed_from_u64(seed)),
+                None => pl.shuffle_albums(),
+            };
+            albums.nested_iter().flatten().copied().collect()
         }
-        match File::create(&path) {
-            Err(e) => {
-                eprintln!("Could not create output file '{}': {}", path.display(), e)
-            }
-            Ok(mut file) => {
-                for path in pl.shuffle().nested_iter() {
-                    if let Err(e) = writeln!(file, "{}", path.display()) {
-                        eprintln!("Could not write to output file '{}': {}", path.display(), e);
-                        break;
-                    }
+        (Grouping::Folder, seed) => {
+            let roots = folder_roots(flags);
+            let folders = match seed {
+                Some(seed) => {
+                    pl.shuffle_by_folder_with_rng(&roots, &mut StdRng::seed_from_u64(seed))
                 }
-            }
+                None => pl.shuffle_by_folder(&roots),
+            };
+            folders.nested_iter().copied().collect()
         }
+        (Grouping::Artist, Some(seed)) => pl.shuffle_seeded(seed).nested_iter().copied().collect(),
+        (Grouping::Artist, None) => pl.shuffle().nested_iter().copied().collect(),
+    };
+    let tracks = shuffled.into_iter();
+    match flags.max_duration {
+        Some(minutes) => {
+            let default = flags.default_duration.unwrap_or(4);
+            pl.take_duration(
+                tracks,
+                Duration::from_secs(minutes * 60),
+                Duration::from_secs(default * 60),
+            )
+            .take(flags.limit.unwrap_or(usize::MAX))
+            .collect()
+        }
+        None => tracks.take(flags.limit.unwrap_or(usize::MAX)).collect(),
+    }
+}
+
+/// Write the playlist (converting the paths for a playlist in the directory `base`)
+fn write_playlist<W: Write>(
+    pl: &Playlist,
+    tracks: &[&PathBuf],
+    format: Format,
+    style: PathStyle,
+    base: &Path,
+    writer: &mut W,
+) -> Result<()> {
+    let paths: Vec<_> = tracks.iter().map(|p| style.apply(p, base)).collect();
+    let entries = paths.iter().zip(tracks).map(|(p, t)| (p, pl.track(t)));
+    formats::write(format, writer, entries)
+}
+
+/// The scanned directories (from both the paths and `--read`, but not STDIN) that the
+/// folders are relative to when grouping by folder
+fn folder_roots(flags: &Artistic_shuffle) -> Vec<PathBuf> {
+    if flags.path.is_empty() && flags.read.is_empty() {
+        return vec![PathBuf::from(".")];
+    }
+    flags
+        .path
+        .iter()
+        .chain(flags.read.iter())
+        .filter(|p| p.as_os_str() != "-" && p.is_dir())
+        .cloned()
+        .collect()
+}
+
+/// The seed for output `index` (the first output uses the seed as is), hashed so that
+/// the outputs of consecutive seeds are unrelated
+fn output_seed(seed: u64, index: u64) -> u64 {
+    match index {
+        0 => seed,
+        _ => hash_seed(&format!("{}/{}", seed, index)),
     }
 }
 
+/// Add a zero-padded index before the extension ("out.m3u" becomes "out_001.m3u")
+fn numbered_path(path: &Path, index: usize, count: usize) -> PathBuf {
+    let width = std::cmp::max(3, count.to_string().len());
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:0width$}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_{:0width$}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Write the playlist to a file (creating the directories if needed)
+fn write_file(
+    pl: &Playlist,
+    tracks: &[&PathBuf],
+    format: Format,
+    style: PathStyle,
+    path: &Path,
+) -> Result<()> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    create_dir_all(base)?;
+    let mut file = File::create(path)?;
+    write_playlist(pl, tracks, format, style, base, &mut file)?;
+    file.flush()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_add() {
-        let mut pl = Playlist::new();
-        pl.add_file(PathBuf::from("a/b"));
-        pl.add_file2(&PathBuf::from("d/e/f"), PathBuf::from("d"));
-        pl.add_dir(PathBuf::from("src"));
-        assert!(pl.0.contains_key(&String::from("a")));
-        assert!(pl.0.contains_key(&String::from("d")));
+    fn test_folder_roots() {
+        let parse = |args: &[&str]| {
+            Artistic_shuffle::from_args(args.iter().map(|a| a.into()).collect()).unwrap()
+        };
+        assert_eq!(folder_roots(&parse(&[])), vec![PathBuf::from(".")]);
+        let dir = std::env::temp_dir();
+        let dir = dir.to_str().unwrap();
+        let file = file!();
+        let flags = parse(&["-", dir, file, "-r", "src", "-r", "Cargo.toml"]);
+        assert_eq!(
+            folder_roots(&flags),
+            vec![PathBuf::from(dir), PathBuf::from("src")]
+        );
     }
 
     #[test]
-    fn test_shuffle() {
-        let mut pl = Playlist::new();
-        pl.add(PathBuf::from("a"), String::from("a"), None);
-        pl.add(PathBuf::from("b"), String::from("a"), Some(199));
-        pl.add(PathBuf::from("c"), String::from("b"), Some(200));
-        pl.add(PathBuf::from("d"), String::from("b"), Some(201));
-
-        let shuff = pl.shuffle().nested_iter().copied().collect::<Vec<_>>();
+    fn test_output_seed() {
+        assert_eq!(output_seed(7, 0), 7);
+        assert_eq!(output_seed(7, 1), output_seed(7, 1));
+        assert_ne!(output_seed(7, 1), 8);
+        assert_ne!(output_seed(7, 1), output_seed(8, 0));
+        assert_ne!(output_seed(7, 2), output_seed(8, 1));
+    }
 
-        assert!(shuff.contains(&&PathBuf::from("a")));
-        assert!(shuff.contains(&&PathBuf::from("b")));
-        assert!(shuff.contains(&&PathBuf::from("c")));
-        assert!(shuff.contains(&&PathBuf::from("d")));
-        assert_eq!(shuff.len(), 6);
+    #[test]
+    fn test_numbered_path() {
+        assert_eq!(
+            numbered_path(Path::new("lists/out.m3u"), 1, 2),
+            PathBuf::from("lists/out_001.m3u")
+        );
+        assert_eq!(
+            numbered_path(Path::new("out"), 12, 1000),
+            PathBuf::from("out_0012")
+        );
     }
 }
@@ -1,22 +1,113 @@
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 
+/// Counts how many times every item occurs
+#[derive(Clone, Debug)]
 pub struct Counter<T: std::hash::Hash + std::cmp::Eq>(HashMap<T, usize>);
 
+/// Shuffles items (that can occur multiple times) so that equal items are spread out
+#[derive(Clone, Debug)]
 pub struct Shuffler<T> {
     items: Vec<T>,
     order: Vec<usize>,
     max_same: usize,
 }
 
+/// A shuffle nested to any depth (e.g. artist → album → track).
+/// Every level is spread out in the final order, with the outer levels taking precedence.
+#[derive(Clone, Debug)]
+pub enum ShuffleTree<T> {
+    Leaf(T),
+    Branch(Shuffler<ShuffleTree<T>>),
+}
+
 pub struct ShufflerIterator<'a, T> {
     shuffle: &'a Shuffler<T>,
     index: usize,
+    back: usize,
 }
 pub struct NestedShufflerIterator<'a, T> {
     shuffle: &'a Shuffler<Shuffler<T>>,
     outer: usize,
     inner: Vec<usize>,
+    remaining: usize,
+}
+
+/// Hash a string into a seed that is stable across runs and platforms (FNV-1a)
+pub fn hash_seed(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Repeatedly sweep through the order, moving repeats further away, until no more moves are needed
+fn sweeps(order: &mut [usize], lookahead: usize, sweep: fn(&mut [usize], usize, usize) -> usize) {
+    let n = order.len();
+    let mut chain: usize = 0;
+    for i in 0..n {
+        chain = std::cmp::max(chain.saturating_sub(1), sweep(order, i, lookahead));
+    }
+    'outer: for _ in 0..5 {
+        for i in 0..n {
+            if chain < 1 {
+                break 'outer;
+            }
+            chain = std::cmp::max(chain - 1, sweep(order, i, lookahead));
+        }
+    }
+}
+
+/// Move repeats of the item at `i` out of the following `lookahead` positions
+#[cfg(any(not(feature = "safe_shuffle"), test))]
+fn sweep_unchecked(order: &mut [usize], i: usize, lookahead: usize) -> usize {
+    let n = order.len();
+    // SAFETY: The size of the list is guaranteed by modulo
+    unsafe {
+        let curr = *order.get_unchecked(i);
+        let mut swp = i;
+        let mut hit = false;
+        for j in (i + 1)..(i + lookahead) {
+            let j = j % n;
+            let mut nex = *order.get_unchecked(j);
+            swp += 1;
+            while curr == nex {
+                hit = true;
+                swp += 1;
+                order.swap(j, swp % n);
+                nex = *order.get_unchecked(j);
+            }
+        }
+        if hit {
+            swp - i
+        } else {
+            0
+        }
+    }
+}
+
+/// Checked reference implementation of [`sweep_unchecked`]
+#[cfg(any(feature = "safe_shuffle", test))]
+fn sweep_checked(order: &mut [usize], i: usize, lookahead: usize) -> usize {
+    let n = order.len();
+    let curr = order[i];
+    let mut swp = i;
+    let mut hit = false;
+    for j in (i + 1)..(i + lookahead) {
+        let j = j % n;
+        swp += 1;
+        while order[j] == curr {
+            hit = true;
+            swp += 1;
+            order.swap(j, swp % n);
+        }
+    }
+    if hit {
+        swp - i
+    } else {
+        0
+    }
 }
 
 impl<T: std::hash::Hash + std::cmp::Eq> Counter<T> {
@@ -24,7 +115,6 @@ impl<T: std::hash::Hash + std::cmp::Eq> Counter<T> {
         Counter(HashMap::new())
     }
 
-    #[allow(dead_code)]
     pub fn add(&mut self, item: T) {
         self.addn(item, 1);
     }
@@ -37,9 +127,35 @@ impl<T: std::hash::Hash + std::cmp::Eq> Counter<T> {
         self.0.insert(item, cnt);
     }
 
-    pub fn iter(&self) -> std::collections::hash_map::Iter<T, usize> {
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, T, usize> {
         self.0.iter()
     }
+
+    /// The sum of all counts
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    /// Remove the item, returning its count (if it existed)
+    pub fn remove(&mut self, item: &T) -> Option<usize> {
+        self.0.remove(item)
+    }
+
+    /// The count of the item (zero if it does not exist)
+    pub fn count(&self, item: &T) -> usize {
+        self.0.get(item).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, item: &T) -> bool {
+        self.0.contains_key(item)
+    }
+
+    /// Add all the items (and counts) of another counter
+    pub fn merge(&mut self, other: Counter<T>) {
+        for (item, num) in other.0 {
+            self.addn(item, num);
+        }
+    }
 }
 
 impl<T: std::hash::Hash + std::cmp::Eq> Default for Counter<T> {
@@ -57,58 +173,120 @@ impl<T> Shuffler<T> {
         }
     }
 
+    /// Add an item that occurs once
     pub fn add(&mut self, item: T) {
         self.order.push(self.items.len());
         self.items.push(item);
     }
 
+    /// Add an item that occurs `num` times
     pub fn addn(&mut self, item: T, num: usize) {
         self.max_same = std::cmp::max(self.max_same, num);
         let i = self.items.len();
-        self.order.extend(std::iter::repeat(i).take(num));
+        self.order.extend(std::iter::repeat_n(i, num));
         self.items.push(item);
     }
 
+    /// Shuffle the items, looking at most `max_lookahead` positions ahead to separate equal items
     pub fn shuffle(&mut self, max_lookahead: usize) {
+        self.shuffle_with_rng(&mut rand::thread_rng(), max_lookahead);
+    }
+
+    /// Shuffle with a reproducible order (given the same items and seed)
+    pub fn shuffle_seeded(&mut self, seed: u64, max_lookahead: usize) {
+        self.shuffle_with_rng(&mut StdRng::seed_from_u64(seed), max_lookahead);
+    }
+
+    /// Shuffle using the given random number generator
+    pub fn shuffle_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R, max_lookahead: usize) {
         if self.order.is_empty() {
             return;
         }
-        let mut prng = rand::thread_rng();
-        self.order.shuffle(&mut prng);
+        self.order.shuffle(rng);
         let n = self.order.len();
+        if self.max_same * 2 > n {
+            // No swap can separate the dominant item, so spread it as evenly as possible
+            self.spread_dominant(rng);
+            return;
+        }
         let lookahead = std::cmp::min(max_lookahead, n / self.max_same);
-        // SAFETY: The size of the list is guaranteed by modulo
-        unsafe {
-            let mut sweep = |i: usize| {
-                let curr = *self.order.get_unchecked(i);
-                let mut swp = i;
-                let mut hit = false;
-                for j in (i + 1)..(i + lookahead) {
-                    let j = j % n;
-                    let mut nex = *self.order.get_unchecked(j);
-                    swp += 1;
-                    while curr == nex {
-                        hit = true;
-                        swp += 1;
-                        self.order.swap(j, swp % n);
-                        nex = *self.order.get_unchecked(j);
-                    }
+        #[cfg(not(feature = "safe_shuffle"))]
+        sweeps(&mut self.order, lookahead, sweep_unchecked);
+        #[cfg(feature = "safe_shuffle")]
+        sweeps(&mut self.order, lookahead, sweep_checked);
+    }
+
+    /// Shuffle so that repeats of an item are at least `min_gap` positions apart (`2` means no
+    /// adjacent repeats). Returns `false` if that is impossible, in which case the repeats are
+    /// placed as far apart as possible.
+    pub fn shuffle_min_gap(&mut self, min_gap: usize) -> bool {
+        self.shuffle_min_gap_with_rng(&mut rand::thread_rng(), min_gap)
+    }
+
+    /// Shuffle with a minimum gap using the given random number generator
+    pub fn shuffle_min_gap_with_rng<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        min_gap: usize,
+    ) -> bool {
+        let mut remaining = vec![0; self.items.len()];
+        for &o in self.order.iter() {
+            remaining[o] += 1;
+        }
+        let mut candidates: Vec<usize> = (0..self.items.len()).collect();
+        let mut last: Vec<Option<usize>> = vec![None; self.items.len()];
+        let mut feasible = true;
+        for pos in 0..self.order.len() {
+            // Ties are broken randomly, otherwise the item with the most remaining is placed first
+            candidates.shuffle(rng);
+            let distance = |i: usize| last[i].map_or(usize::MAX, |l| pos - l);
+            let next = candidates
+                .iter()
+                .copied()
+                .filter(|&i| remaining[i] > 0 && distance(i) >= min_gap)
+                .max_by_key(|&i| remaining[i]);
+            let next = match next {
+                Some(i) => i,
+                None => {
+                    feasible = false;
+                    candidates
+                        .iter()
+                        .copied()
+                        .filter(|&i| remaining[i] > 0)
+                        .max_by_key(|&i| (distance(i), remaining[i]))
+                        .expect("remaining items")
                 }
-                swp = if hit { swp - i } else { 0 };
-                swp
             };
-            let mut chain: usize = 0;
-            for i in 0..n {
-                chain = std::cmp::max(chain.saturating_sub(1), sweep(i));
-            }
-            'outer: for _ in 0..5 {
-                for i in 0..n {
-                    if chain < 1 {
-                        break 'outer;
-                    }
-                    chain = std::cmp::max(chain - 1, sweep(i));
-                }
-            }
+            self.order[pos] = next;
+            remaining[next] -= 1;
+            last[next] = Some(pos);
+        }
+        feasible
+    }
+
+    /// Split the dominant item into (almost) equally long runs, separated by the other items.
+    /// This assumes that `self.order` is already shuffled.
+    fn spread_dominant<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let mut count = vec![0; self.items.len()];
+        for &o in self.order.iter() {
+            count[o] += 1;
+        }
+        let dominant = (0..count.len()).max_by_key(|&i| count[i]).unwrap_or(0);
+        let others: Vec<usize> = self
+            .order
+            .iter()
+            .copied()
+            .filter(|&o| o != dominant)
+            .collect();
+        let runs = others.len() + 1;
+        let mut lengths = vec![count[dominant] / runs; runs];
+        for i in rand::seq::index::sample(rng, runs, count[dominant] % runs) {
+            lengths[i] += 1;
+        }
+        self.order.clear();
+        for (len, other) in lengths.iter().zip(others.iter().map(Some).chain([None])) {
+            self.order.extend(std::iter::repeat_n(dominant, *len));
+            self.order.extend(other);
         }
     }
 
@@ -132,27 +310,127 @@ impl<T> Shuffler<T> {
         self.order.is_empty()
     }
 
-    pub fn iter(&self) -> ShufflerIterator<T> {
+    pub fn iter(&self) -> ShufflerIterator<'_, T> {
         ShufflerIterator {
             shuffle: self,
             index: 0,
+            back: self.len(),
+        }
+    }
+
+    /// Shuffle up to `attempts` times and keep the order with the best [`Shuffler::score`]
+    pub fn optimal_shuffle(&mut self, attempts: usize, max_lookahead: usize) {
+        self.shuffle(max_lookahead);
+        let mut best = (self.score(), self.order.clone());
+        for _ in 1..attempts {
+            if best.0 >= 1.0 {
+                break;
+            }
+            self.shuffle(max_lookahead);
+            let score = self.score();
+            if score > best.0 {
+                best = (score, self.order.clone());
+            }
+        }
+        self.order = best.1;
+    }
+
+    /// The number of positions where the next item is the same item
+    pub fn adjacency_violations(&self) -> usize {
+        self.order.windows(2).filter(|w| w[0] == w[1]).count()
+    }
+
+    /// The smallest distance between two occurrences of the same item (`None` if no item repeats)
+    pub fn min_gap(&self) -> Option<usize> {
+        let mut last = vec![None; self.items.len()];
+        let mut gap = None;
+        for (i, &o) in self.order.iter().enumerate() {
+            if let Some(j) = last[o] {
+                gap = Some(gap.map_or(i - j, |g: usize| g.min(i - j)));
+            }
+            last[o] = Some(i);
+        }
+        gap
+    }
+
+    /// Measure how evenly the repeated items are spread, between 0.0 and 1.0 (perfectly uniform).
+    /// This is the average (over the repeated items) of the smallest distance between two
+    /// occurrences of an item divided by the distance in a perfectly uniform spread.
+    pub fn score(&self) -> f64 {
+        let n = self.order.len();
+        let mut last = vec![None; self.items.len()];
+        let mut min_gap = vec![usize::MAX; self.items.len()];
+        let mut count = vec![0; self.items.len()];
+        for (i, &o) in self.order.iter().enumerate() {
+            if let Some(j) = last[o] {
+                min_gap[o] = std::cmp::min(min_gap[o], i - j);
+            }
+            last[o] = Some(i);
+            count[o] += 1;
+        }
+        let (sum, repeated) = count
+            .iter()
+            .zip(min_gap.iter())
+            .filter(|(c, _)| **c > 1)
+            .fold((0.0, 0), |(sum, repeated), (c, gap)| {
+                let ideal = (n / c) as f64;
+                (sum + (*gap as f64 / ideal).min(1.0), repeated + 1)
+            });
+        if repeated == 0 {
+            1.0
+        } else {
+            sum / repeated as f64
         }
     }
 }
 
+impl<T: Clone> Shuffler<T> {
+    /// Consume the shuffler and return the items in the shuffled order.
+    /// Items that occur multiple times are cloned (except for the last occurrence, which is moved).
+    pub fn into_vec(self) -> Vec<T> {
+        let mut remaining = vec![0; self.items.len()];
+        for &o in self.order.iter() {
+            remaining[o] += 1;
+        }
+        let mut items: Vec<Option<T>> = self.items.into_iter().map(Some).collect();
+        let mut out = Vec::with_capacity(self.order.len());
+        for o in self.order {
+            remaining[o] -= 1;
+            let item = if remaining[o] == 0 {
+                items[o].take()
+            } else {
+                items[o].clone()
+            };
+            out.extend(item);
+        }
+        out
+    }
+}
+
 impl<T> Shuffler<Shuffler<T>> {
     pub fn nested_shuffle(&mut self, max_lookahead: usize) {
+        self.nested_shuffle_with_rng(&mut rand::thread_rng(), max_lookahead);
+    }
+
+    /// Shuffle with a reproducible order (given the same items and seed)
+    pub fn nested_shuffle_seeded(&mut self, seed: u64, max_lookahead: usize) {
+        self.nested_shuffle_with_rng(&mut StdRng::seed_from_u64(seed), max_lookahead);
+    }
+
+    /// Shuffle using the given random number generator
+    pub fn nested_shuffle_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R, max_lookahead: usize) {
         for rnd in self.items.iter_mut() {
-            rnd.shuffle(max_lookahead);
+            rnd.shuffle_with_rng(rng, max_lookahead);
         }
-        self.shuffle(max_lookahead);
+        self.shuffle_with_rng(rng, max_lookahead);
     }
 
-    pub fn nested_iter(&self) -> NestedShufflerIterator<T> {
+    pub fn nested_iter(&self) -> NestedShufflerIterator<'_, T> {
         NestedShufflerIterator {
             shuffle: self,
             outer: 0,
-            inner: std::iter::repeat(0).take(self.items.len()).collect(),
+            inner: std::iter::repeat_n(0, self.items.len()).collect(),
+            remaining: self.items.iter().map(|s| s.len()).sum(),
         }
     }
 
@@ -172,16 +450,130 @@ where
     }
 }
 
+impl<T> ShuffleTree<T> {
+    /// An empty branch
+    pub fn branch() -> ShuffleTree<T> {
+        ShuffleTree::Branch(Shuffler::new())
+    }
+
+    /// Add a leaf (occurring `num` times) to a branch (does nothing for a leaf)
+    pub fn add_leaf(&mut self, item: T, num: usize) {
+        if let ShuffleTree::Branch(shuffle) = self {
+            shuffle.addn(ShuffleTree::Leaf(item), num);
+        }
+    }
+
+    /// Add a subtree to a branch (does nothing for a leaf)
+    pub fn add_branch(&mut self, tree: ShuffleTree<T>) {
+        if let ShuffleTree::Branch(shuffle) = self {
+            let len = tree.len();
+            shuffle.addn(tree, len);
+        }
+    }
+
+    /// The number of items in the flattened order
+    pub fn len(&self) -> usize {
+        match self {
+            ShuffleTree::Leaf(_) => 1,
+            ShuffleTree::Branch(shuffle) => shuffle.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn shuffle(&mut self, max_lookahead: usize) {
+        self.shuffle_with_rng(&mut rand::thread_rng(), max_lookahead);
+    }
+
+    /// Shuffle with a reproducible order (given the same items and seed)
+    pub fn shuffle_seeded(&mut self, seed: u64, max_lookahead: usize) {
+        self.shuffle_with_rng(&mut StdRng::seed_from_u64(seed), max_lookahead);
+    }
+
+    /// Shuffle every level (innermost first) using the given random number generator
+    pub fn shuffle_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R, max_lookahead: usize) {
+        if let ShuffleTree::Branch(shuffle) = self {
+            for item in shuffle.items.iter_mut() {
+                item.shuffle_with_rng(rng, max_lookahead);
+            }
+            shuffle.shuffle_with_rng(rng, max_lookahead);
+        }
+    }
+
+    /// The flattened order, taking the next item from the subtrees in the order of the branch
+    pub fn flatten(&self) -> Vec<&T> {
+        match self {
+            ShuffleTree::Leaf(item) => vec![item],
+            ShuffleTree::Branch(shuffle) => {
+                let mut subtrees: Vec<_> = shuffle
+                    .items
+                    .iter()
+                    .map(|item| match item {
+                        ShuffleTree::Leaf(_) => None,
+                        ShuffleTree::Branch(_) => Some(item.flatten().into_iter()),
+                    })
+                    .collect();
+                let mut out = Vec::with_capacity(shuffle.len());
+                for &o in shuffle.order.iter() {
+                    match (&shuffle.items[o], &mut subtrees[o]) {
+                        (ShuffleTree::Leaf(item), _) => out.push(item),
+                        (_, Some(subtree)) => out.extend(subtree.next()),
+                        _ => {}
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    pub fn iter(&self) -> std::vec::IntoIter<&T> {
+        self.flatten().into_iter()
+    }
+}
+
+/// Consuming iteration yields every item once per occurrence in the shuffled order,
+/// see [`Shuffler::into_vec`].
+impl<T: Clone> IntoIterator for Shuffler<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
 impl<'a, T> Iterator for ShufflerIterator<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.back {
+            return None;
+        }
         let out = self.shuffle.get(self.index);
         self.index += 1;
         out
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back.saturating_sub(self.index);
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for ShufflerIterator<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.index >= self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.shuffle.get(self.back)
+    }
 }
 
+impl<T> ExactSizeIterator for ShufflerIterator<'_, T> {}
+
 impl<'a, T> Iterator for NestedShufflerIterator<'a, T> {
     type Item = &'a T;
 
@@ -196,10 +588,19 @@ impl<'a, T> Iterator for NestedShufflerIterator<'a, T> {
         }
         let out = self.shuffle.get(self.outer)?.get(j);
         self.outer += 1;
+        if out.is_some() {
+            self.remaining = self.remaining.saturating_sub(1);
+        }
         out
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl<T> ExactSizeIterator for NestedShufflerIterator<'_, T> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -216,6 +617,75 @@ mod tests {
         assert_eq!(iter, vec![(0, 3), (2, 1), (5, 10)]);
     }
 
+    #[test]
+    fn test_counter_total() {
+        let mut cnt = Counter::new();
+        assert_eq!(cnt.total(), 0);
+        cnt.add(0);
+        cnt.addn(5, 10);
+        cnt.addn(0, 2);
+        assert_eq!(cnt.total(), 13);
+    }
+
+    #[test]
+    fn test_counter_clone() {
+        let mut cnt = Counter::new();
+        cnt.addn("a", 2);
+        let copy = cnt.clone();
+        cnt.add("a");
+        cnt.add("b");
+        assert_eq!(copy.count(&"a"), 2);
+        assert!(!copy.contains(&"b"));
+        assert_eq!(cnt.count(&"a"), 3);
+        assert_eq!(format!("{:?}", copy), r#"Counter({"a": 2})"#);
+    }
+
+    #[test]
+    fn test_counter_lookup() {
+        let mut cnt = Counter::new();
+        cnt.add(2);
+        cnt.addn(5, 10);
+        assert_eq!(cnt.count(&5), 10);
+        assert_eq!(cnt.count(&1), 0);
+        assert!(cnt.contains(&2));
+        assert!(!cnt.contains(&1));
+    }
+
+    #[test]
+    fn test_counter_remove() {
+        let mut cnt = Counter::new();
+        cnt.addn("a", 2);
+        cnt.add("b");
+        assert_eq!(cnt.remove(&"a"), Some(2));
+        assert_eq!(cnt.remove(&"a"), None);
+        assert_eq!(cnt.remove(&"c"), None);
+        assert!(!cnt.contains(&"a"));
+        assert_eq!(cnt.count(&"a"), 0);
+        assert_eq!(cnt.total(), 1);
+    }
+
+    #[test]
+    fn test_counter_merge() {
+        let mut a = Counter::new();
+        a.addn("a", 2);
+        a.add("b");
+        let mut b = Counter::new();
+        b.add("b");
+        b.addn("c", 3);
+        a.merge(b);
+        assert_eq!(a.count(&"a"), 2);
+        assert_eq!(a.count(&"b"), 2);
+        assert_eq!(a.count(&"c"), 3);
+        assert_eq!(a.total(), 7);
+    }
+
+    #[test]
+    fn test_hash_seed() {
+        assert_eq!(hash_seed(""), 0xcbf29ce484222325);
+        assert_eq!(hash_seed("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(hash_seed("ab"), hash_seed("ba"));
+    }
+
     #[test]
     fn test_shuffle() {
         for _ in 0..10 {
@@ -228,6 +698,229 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_exact_size() {
+        let mut ts = Shuffler::new();
+        for i in 0..4 {
+            ts.addn(i, i + 1);
+        }
+        ts.shuffle(10);
+        let mut iter = ts.iter();
+        assert_eq!(iter.len(), 10);
+        iter.next();
+        assert_eq!(iter.len(), 9);
+        iter.nth(8);
+        assert_eq!(iter.len(), 0);
+        iter.next();
+        assert_eq!(iter.len(), 0);
+    }
+
+    #[test]
+    fn test_sweep_checked() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for lookahead in 2..6 {
+            let mut order: Vec<usize> =
+                (0..6).flat_map(|i| std::iter::repeat_n(i, i + 1)).collect();
+            order.shuffle(&mut rng);
+            let mut checked = order.clone();
+            sweeps(&mut order, lookahead, sweep_unchecked);
+            sweeps(&mut checked, lookahead, sweep_checked);
+            assert_eq!(order, checked);
+        }
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut ts = Shuffler::new();
+        for i in 0..4 {
+            ts.addn(i, i + 1);
+        }
+        ts.shuffle(10);
+        let copy = ts.clone();
+        assert!(ts.iter().eq(copy.iter()));
+        ts.shuffle(10);
+        assert_eq!(copy.len(), 10);
+    }
+
+    #[test]
+    fn test_double_ended() {
+        let mut ts = Shuffler::new();
+        for i in 0..3 {
+            ts.addn(i, i + 1);
+        }
+        ts.shuffle(10);
+        let forward: Vec<_> = ts.iter().collect();
+        let reverse: Vec<_> = ts.iter().rev().collect();
+        assert!(forward.iter().eq(reverse.iter().rev()));
+        let mut iter = ts.iter();
+        let mut taken = 0;
+        while iter.len() > 0 {
+            assert_eq!(iter.len(), 6 - taken);
+            let item = if taken % 2 == 0 {
+                iter.next()
+            } else {
+                iter.next_back()
+            };
+            assert!(item.is_some());
+            taken += 1;
+        }
+        assert_eq!(taken, 6);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.len(), 0);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut ts = Shuffler::new();
+        ts.addn(String::from("a"), 3);
+        ts.add(String::from("b"));
+        ts.shuffle(10);
+        let expected: Vec<String> = ts.iter().cloned().collect();
+        let mut owned = Vec::new();
+        for item in ts {
+            owned.push(item);
+        }
+        assert_eq!(owned, expected);
+        let mut ts = Shuffler::new();
+        ts.addn(vec![1], 2);
+        ts.add(vec![2]);
+        ts.shuffle(10);
+        let expected: Vec<Vec<i32>> = ts.iter().cloned().collect();
+        assert_eq!(ts.into_vec(), expected);
+        // Repeated items are cloned, the last occurrence is moved
+        let mut ts = Shuffler::new();
+        ts.addn(std::rc::Rc::new('a'), 3);
+        ts.shuffle(10);
+        let owned: Vec<_> = ts.into_iter().collect();
+        assert_eq!(owned.len(), 3);
+        assert_eq!(std::rc::Rc::strong_count(&owned[0]), 3);
+    }
+
+    #[test]
+    fn test_shuffle_min_gap() {
+        let min_gap = |ts: &Shuffler<char>| {
+            let items: Vec<_> = ts.iter().collect();
+            (1..items.len())
+                .flat_map(|d| (d..items.len()).map(move |i| (d, i)))
+                .filter(|&(d, i)| items[i] == items[i - d])
+                .map(|(d, _)| d)
+                .min()
+                .unwrap_or(usize::MAX)
+        };
+        for _ in 0..10 {
+            let mut ts = Shuffler::new();
+            for c in ['a', 'b', 'c', 'd'] {
+                ts.addn(c, 3);
+            }
+            ts.add('e');
+            assert!(ts.shuffle_min_gap(4));
+            assert_eq!(ts.len(), 13);
+            assert!(min_gap(&ts) >= 4);
+        }
+        let mut ts = Shuffler::new();
+        ts.addn('a', 5);
+        ts.addn('b', 2);
+        assert!(!ts.shuffle_min_gap(3));
+        assert_eq!(ts.iter().filter(|&&c| c == 'a').count(), 5);
+        assert_eq!(min_gap(&ts), 1);
+        let mut ts = Shuffler::new();
+        ts.addn('a', 3);
+        ts.addn('b', 2);
+        assert!(ts.shuffle_min_gap(2));
+        assert_eq!(ts.iter().collect::<String>(), "ababa");
+    }
+
+    #[test]
+    fn test_dominant() {
+        for _ in 0..20 {
+            let mut shuffle = Shuffler::new();
+            shuffle.addn('a', 8);
+            shuffle.add('b');
+            shuffle.add('c');
+            shuffle.shuffle(10);
+            assert_eq!(shuffle.len(), 10);
+            assert_eq!(shuffle.iter().filter(|&&c| c == 'a').count(), 8);
+            let mut run = 0;
+            let mut max_run = 0;
+            for &c in shuffle.iter() {
+                run = if c == 'a' { run + 1 } else { 0 };
+                max_run = std::cmp::max(max_run, run);
+            }
+            assert_eq!(max_run, 3);
+        }
+        let mut shuffle = Shuffler::new();
+        shuffle.addn('a', 5);
+        shuffle.shuffle(10);
+        assert_eq!(shuffle.len(), 5);
+    }
+
+    #[test]
+    fn test_adjacency() {
+        let mut ts = Shuffler::new();
+        ts.addn('a', 3);
+        ts.addn('b', 2);
+        ts.add('c');
+        ts.order = vec![0, 1, 0, 2, 0, 1];
+        assert_eq!(ts.adjacency_violations(), 0);
+        assert_eq!(ts.min_gap(), Some(2));
+        ts.order = vec![0, 0, 1, 2, 1, 0];
+        assert_eq!(ts.adjacency_violations(), 1);
+        assert_eq!(ts.min_gap(), Some(1));
+        ts.order = vec![0, 0, 0, 1, 1, 2];
+        assert_eq!(ts.adjacency_violations(), 3);
+        ts.order = vec![0, 1, 2, 0, 1, 0];
+        assert_eq!(ts.min_gap(), Some(2));
+        ts.order = vec![0, 1, 2];
+        assert_eq!(ts.adjacency_violations(), 0);
+        assert_eq!(ts.min_gap(), None);
+        assert_eq!(Shuffler::<u8>::new().min_gap(), None);
+    }
+
+    #[test]
+    fn test_score() {
+        let mut ts = Shuffler::new();
+        ts.addn('a', 2);
+        ts.addn('b', 2);
+        ts.add('c');
+        ts.order = vec![0, 1, 2, 0, 1];
+        assert_eq!(ts.score(), 1.0);
+        ts.order = vec![0, 0, 1, 2, 1];
+        assert_eq!(ts.score(), 0.75);
+        ts.order = vec![0, 0, 1, 1, 2];
+        assert_eq!(ts.score(), 0.5);
+        assert_eq!(Shuffler::<u8>::new().score(), 1.0);
+    }
+
+    #[test]
+    fn test_optimal_shuffle() {
+        for _ in 0..10 {
+            let mut ts = Shuffler::new();
+            for i in 0..5 {
+                ts.addn(i, 2);
+            }
+            ts.optimal_shuffle(50, 10);
+            assert_eq!(ts.len(), 10);
+            assert!(ts.score() > 0.5);
+        }
+    }
+
+    #[test]
+    fn test_shuffle_with_rng() {
+        let shuffled = |seed: u64| {
+            let mut ts = Shuffler::new();
+            for i in 0..20 {
+                let mut ts2 = Shuffler::new();
+                ts2.addn(i, i % 3 + 1);
+                ts.nested_add(ts2);
+            }
+            ts.nested_shuffle_with_rng(&mut StdRng::seed_from_u64(seed), 10);
+            ts.nested_iter().copied().collect::<Vec<_>>()
+        };
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), shuffled(43));
+    }
+
     #[test]
     fn test_nested_shuffle() {
         let mut ts = Shuffler::new();
@@ -247,4 +940,80 @@ mod tests {
             .zip(ts.nested_iter().skip(1))
             .all(|(a, b)| a != b));
     }
+
+    #[test]
+    fn test_shuffle_tree() {
+        for seed in 0..10 {
+            let mut tree = ShuffleTree::branch();
+            for artist in 0..4 {
+                let mut albums = ShuffleTree::branch();
+                for album in 0..2 {
+                    let mut tracks = ShuffleTree::branch();
+                    for track in 0..3 {
+                        tracks.add_leaf((artist, album, track), 1);
+                    }
+                    albums.add_branch(tracks);
+                }
+                tree.add_branch(albums);
+            }
+            tree.shuffle_seeded(seed, 10);
+            let items = tree.flatten();
+            assert_eq!(items.len(), 24);
+            assert_eq!(tree.iter().len(), 24);
+            let mut sorted = items.clone();
+            sorted.sort_unstable();
+            sorted.dedup();
+            assert_eq!(sorted.len(), 24);
+            assert!(items.windows(2).all(|w| w[0].0 != w[1].0));
+            for artist in 0..4 {
+                let albums: Vec<_> = items.iter().filter(|i| i.0 == artist).collect();
+                assert_eq!(albums.len(), 6);
+                assert!(albums.windows(2).all(|w| w[0].1 != w[1].1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut ts = Shuffler::new();
+        ts.addn("a", 2);
+        assert!(format!("{:?}", ts).starts_with("Shuffler {"));
+        let mut tree = ShuffleTree::branch();
+        tree.add_leaf("a", 1);
+        assert!(format!("{:?}", tree).starts_with("Branch(Shuffler {"));
+    }
+
+    #[test]
+    fn test_nested_exact_size() {
+        let mut ts = Shuffler::new();
+        for i in 0..4 {
+            let mut ts2 = Shuffler::new();
+            ts2.addn(i, 4);
+            ts.nested_add(ts2);
+        }
+        ts.nested_shuffle(10);
+        let mut iter = ts.nested_iter();
+        assert_eq!(iter.len(), 16);
+        iter.next();
+        assert_eq!(iter.len(), 15);
+    }
+
+    #[test]
+    fn test_nested_size_hint() {
+        let mut ts = Shuffler::new();
+        for i in 0..3 {
+            let mut ts2 = Shuffler::new();
+            ts2.addn(i, i + 2);
+            ts.nested_add(ts2);
+        }
+        ts.nested_shuffle(10);
+        let mut iter = ts.nested_iter();
+        for remaining in (0..=9).rev() {
+            assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            assert_eq!(iter.next().is_some(), remaining > 0);
+        }
+        let items: Vec<_> = ts.nested_iter().collect();
+        assert_eq!(items.len(), 9);
+        assert!(items.capacity() >= 9);
+    }
 }
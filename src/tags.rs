@@ -1,58 +1,320 @@
-use lofty::{read_from_path, ItemKey, Tag, TagType, TaggedFileExt};
+use lofty::{read_from_path, AudioFile, ItemKey, Tag, TagType, TaggedFileExt};
 use std::path::{Component, Path};
+use std::time::Duration;
 
-pub fn get_tags(path: &Path) -> (String, Option<u8>) {
+/// The metadata of a track
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackInfo {
+    /// The artist (guessed from the path if the tags have no artist)
+    pub artist: String,
+    /// The rating on a 0-255 scale (where 4/5 stars is about 200)
+    pub rating: Option<u8>,
+    pub genre: Option<String>,
+    pub title: Option<String>,
+    /// The length of the audio. This is best-effort: it is `None` for files that are not
+    /// recognised as audio, and it might be missing or approximate for some formats.
+    pub duration: Option<Duration>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    /// The position of the track on the album
+    pub track_number: Option<u32>,
+    /// How many times the track has been played (if the player stores it in the tags)
+    pub play_count: Option<u32>,
+    /// The tags have no artist, so it was guessed from the path
+    pub guessed_artist: bool,
+}
+
+/// Settings for how the tags are interpreted
+#[derive(Clone, Debug)]
+pub struct TagConfig {
+    /// Separators between multiple artists in one tag (matched case-insensitively)
+    pub artist_separators: Vec<String>,
+    /// Add tracks with multiple artists to every artist (instead of only the first one)
+    pub all_artists: bool,
+    /// Album artists that mark a compilation (these tracks are grouped by album instead)
+    pub various_artists: Vec<String>,
+    /// The tag fields that are checked (in order) for the artist
+    pub artist_keys: Vec<ItemKey>,
+    /// Separator between the artist and the title in filenames (e.g. "Artist - Title.mp3")
+    pub filename_separator: String,
+}
+
+/// The names of the tag fields that can be used for the artist
+const ARTIST_KEYS: [(&str, ItemKey); 9] = [
+    ("trackartist", ItemKey::TrackArtist),
+    ("albumartist", ItemKey::AlbumArtist),
+    ("originalartist", ItemKey::OriginalArtist),
+    ("performer", ItemKey::Performer),
+    ("composer", ItemKey::Composer),
+    ("conductor", ItemKey::Conductor),
+    ("arranger", ItemKey::Arranger),
+    ("lyricist", ItemKey::Lyricist),
+    ("remixer", ItemKey::Remixer),
+];
+
+impl Default for TagConfig {
+    fn default() -> Self {
+        // A bare "/" is not included since it is part of names such as "AC/DC"
+        let separators = [
+            " (feat. ",
+            " feat. ",
+            " feat ",
+            " (ft. ",
+            " ft. ",
+            " featuring ",
+            ";",
+            " / ",
+            " & ",
+            " x ",
+        ];
+        TagConfig {
+            artist_separators: separators.iter().map(|s| String::from(*s)).collect(),
+            all_artists: false,
+            various_artists: [
+                "Various Artists",
+                "Various",
+                "VA",
+                "V.A.",
+                "Varios Artistas",
+                "Verschiedene Interpreten",
+                "Artistes Divers",
+                "Artisti Vari",
+                "Diverse Artiesten",
+                "Blandade Artister",
+            ]
+            .iter()
+            .map(|s| String::from(*s))
+            .collect(),
+            artist_keys: ARTIST_KEYS[..5].iter().map(|(_, k)| k.clone()).collect(),
+            filename_separator: String::from(" - "),
+        }
+    }
+}
+
+impl TagConfig {
+    /// Parse a comma separated list of artist fields (e.g. "composer,albumartist,trackartist")
+    pub fn parse_artist_keys(list: &str) -> Result<Vec<ItemKey>, String> {
+        list.split(',')
+            .map(|name| {
+                let name = name.trim().to_lowercase();
+                ARTIST_KEYS
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, k)| k.clone())
+                    .ok_or_else(|| {
+                        let names: Vec<_> = ARTIST_KEYS.iter().map(|(n, _)| *n).collect();
+                        format!(
+                            "Unknown artist key '{}' (expected one of: {})",
+                            name,
+                            names.join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Identifies the settings that affect the parsed tags (used to invalidate cached tags)
+    pub fn cache_id(&self) -> String {
+        format!("{}|{}", self.artist_key_names(), self.filename_separator)
+    }
+
+    /// The artist fields as a comma separated list
+    pub fn artist_key_names(&self) -> String {
+        let names: Vec<_> = self
+            .artist_keys
+            .iter()
+            .filter_map(|k| ARTIST_KEYS.iter().find(|(_, k2)| k == k2).map(|(n, _)| *n))
+            .collect();
+        names.join(",")
+    }
+
+    /// Is the album artist one of the "Various Artists" names (i.e. the track is from a compilation)
+    pub fn is_various_artists(&self, album_artist: &str) -> bool {
+        let key = normalize_artist(album_artist);
+        self.various_artists
+            .iter()
+            .any(|va| normalize_artist(va) == key)
+    }
+}
+
+/// Split a tag with multiple artists (e.g. "A feat. B") into the individual artists
+pub fn split_artists<'a, S: AsRef<str>>(artist: &'a str, separators: &[S]) -> Vec<&'a str> {
+    // ASCII lowercasing keeps the byte offsets intact
+    let lower = artist.to_ascii_lowercase();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < artist.len() {
+        let sep = separators
+            .iter()
+            .map(|s| s.as_ref())
+            .find(|s| !s.is_empty() && lower[i..].starts_with(&s.to_ascii_lowercase()));
+        match sep {
+            Some(sep) => {
+                parts.push(&artist[start..i]);
+                i += sep.len();
+                start = i;
+            }
+            None => i += lower[i..].chars().next().map_or(1, |c| c.len_utf8()),
+        }
+    }
+    parts.push(&artist[start..]);
+    parts
+        .into_iter()
+        .map(|p| p.trim_matches(|c: char| c.is_whitespace() || "()[]".contains(c)))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Convert a rating (0-255) into 0-5 stars (using the same thresholds as Windows)
+pub fn rating_to_stars(rating: u8) -> u8 {
+    match rating {
+        0 => 0,
+        1..=63 => 1,
+        64..=127 => 2,
+        128..=195 => 3,
+        196..=249 => 4,
+        250..=255 => 5,
+    }
+}
+
+/// Normalise an artist name into a key for grouping, so that e.g. "The Beatles",
+/// "Beatles, The", and "BEATLES" are the same artist.
+/// The case folding does not depend on the locale: "İ", "I", and "ı" all become "i",
+/// "ß" becomes "ss", and accents (also as combining characters) are removed.
+pub fn normalize_artist(artist: &str) -> String {
+    let mut key = String::with_capacity(artist.len());
+    for word in artist.split_whitespace() {
+        if !key.is_empty() {
+            key.push(' ');
+        }
+        for c in word.chars().flat_map(char::to_lowercase) {
+            // Combining accents (e.g. the dot that "İ" keeps when lowercased)
+            if ('\u{300}'..='\u{36f}').contains(&c) {
+                continue;
+            }
+            match remove_diacritic(c) {
+                Some(s) => key.push_str(s),
+                None => key.push(c),
+            }
+        }
+    }
+    if let Some(rest) = key
+        .strip_suffix(", the")
+        .or_else(|| key.strip_prefix("the "))
+    {
+        key = String::from(rest.trim_end_matches(','));
+    }
+    key
+}
+
+// Decompose common (lowercase) latin letters with diacritics
+fn remove_diacritic(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' | 'đ' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+        'ğ' => "g",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => "i",
+        'ł' | 'ľ' => "l",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+        'œ' => "oe",
+        'ř' => "r",
+        'ś' | 'š' | 'ş' => "s",
+        'ς' => "σ",
+        'ß' => "ss",
+        'ť' | 'ţ' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+pub fn get_tags(path: &Path) -> TrackInfo {
+    get_tags_with_config(path, &TagConfig::default())
+}
+
+/// Read the tags of a file, using the artist fields from the config
+pub fn get_tags_with_config(path: &Path, config: &TagConfig) -> TrackInfo {
+    let mut info = TrackInfo::default();
     let mut artist = None;
-    let mut rating = None;
     if let Ok(tagged_file) = read_from_path(path) {
-        let mut artist2 = None;
-        if let Some(tag) = tagged_file.primary_tag() {
-            artist2 = parse_artist(tag);
-            rating = parse_rating(tag);
-        }
-        for tag in tagged_file.tags() {
-            if artist2.is_some() && rating.is_some() {
-                break;
+        // Prefer the primary tag, but fill in missing fields from the other tags
+        for tag in tagged_file
+            .primary_tag()
+            .into_iter()
+            .chain(tagged_file.tags())
+        {
+            if artist.is_none() {
+                artist = parse_artist(tag, &config.artist_keys).map(String::from);
             }
-            if artist2.is_none() {
-                artist2 = parse_artist(tag);
+            if info.rating.is_none() {
+                info.rating = parse_rating(tag);
             }
-            if rating.is_none() {
-                rating = parse_rating(tag);
+            fill_string(&mut info.genre, tag, &ItemKey::Genre);
+            fill_string(&mut info.title, tag, &ItemKey::TrackTitle);
+            fill_string(&mut info.album, tag, &ItemKey::AlbumTitle);
+            fill_string(&mut info.album_artist, tag, &ItemKey::AlbumArtist);
+            if info.play_count.is_none() {
+                info.play_count = parse_play_count(tag);
+            }
+            if info.track_number.is_none() {
+                info.track_number = tag
+                    .get_string(&ItemKey::TrackNumber)
+                    .and_then(parse_track_number);
             }
         }
-        artist = artist2.map(String::from);
+        info.duration = Some(tagged_file.properties().duration()).filter(|d| !d.is_zero());
+    }
+    info.guessed_artist = artist.is_none();
+    info.artist =
+        artist.unwrap_or_else(|| parse_artist_from_path(path, &config.filename_separator));
+    info
+}
+
+// Track numbers are sometimes written as "3/12"
+fn parse_track_number(s: &str) -> Option<u32> {
+    s.split('/').next()?.trim().parse().ok()
+}
+
+fn fill_string(field: &mut Option<String>, tag: &Tag, key: &ItemKey) {
+    if field.is_none() {
+        *field = tag.get_string(key).map(String::from);
     }
-    (
-        artist.unwrap_or_else(|| parse_artist_from_path(path)),
-        rating,
-    )
 }
 
-fn parse_artist(tag: &Tag) -> Option<&str> {
-    tag.get_string(&ItemKey::TrackArtist)
-        .or_else(|| tag.get_string(&ItemKey::AlbumArtist))
-        .or_else(|| tag.get_string(&ItemKey::OriginalArtist))
-        .or_else(|| tag.get_string(&ItemKey::Performer))
-        .or_else(|| tag.get_string(&ItemKey::Composer))
+fn parse_artist<'a>(tag: &'a Tag, keys: &[ItemKey]) -> Option<&'a str> {
+    keys.iter().find_map(|k| tag.get_string(k))
 }
 
 fn parse_rating(tag: &Tag) -> Option<u8> {
-    tag.get_item_ref(&ItemKey::Popularimeter)?;
     match tag.tag_type() {
-        TagType::APE => todo!(),
-        TagType::ID3v1 => todo!(),
+        TagType::APE => parse_rating_ape(tag),
+        TagType::AIFFText => parse_rating_aiff(tag),
+        TagType::VorbisComments => parse_rating_vorbis(tag),
+        _ if tag.get_item_ref(&ItemKey::Popularimeter).is_none() => None,
+        // ID3v1 has no rating field (the last comment byte is the v1.1 track number)
+        TagType::ID3v1 => None,
         TagType::ID3v2 => parse_rating_binaryu8(tag),
         TagType::MP4ilst => parse_rating_text100(tag),
-        TagType::VorbisComments => parse_rating_text100(tag),
-        TagType::RIFFInfo => todo!(),
-        TagType::AIFFText => todo!(),
-        _ => todo!(),
+        TagType::RIFFInfo => parse_rating_riff(tag),
+        // Unknown tag types are treated as unrated
+        _ => None,
     }
 }
 
+// POPM frames are "<email>\0<rating><counter>"
 fn parse_rating_binaryu8(tag: &Tag) -> Option<u8> {
     let bin = tag.get_binary(&ItemKey::Popularimeter, false)?;
+    if let Some(end) = bin.iter().position(|b| *b == 0) {
+        if &bin[..end] == b"Windows Media Player 9 Series" {
+            return bin.get(end + 1).map(|r| parse_rating_wmp(*r));
+        }
+    }
     if bin.len() > 5 {
         Some(bin[bin.len() - 5])
     } else {
@@ -60,48 +322,612 @@ fn parse_rating_binaryu8(tag: &Tag) -> Option<u8> {
     }
 }
 
+// Windows Media Player writes 1, 64, 128, 196, and 255 for 1-5 stars
+fn parse_rating_wmp(rating: u8) -> u8 {
+    let stars = match rating {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        224..=255 => 5,
+    };
+    stars * 51
+}
+
+// APE has no standard rating key, so look for "Rating" (0-5 stars, 0-100, or 0-255)
+fn parse_rating_ape(tag: &Tag) -> Option<u8> {
+    let s = tag.get_string(&ItemKey::Popularimeter).or_else(|| {
+        tag.items()
+            .find(|i| match i.key() {
+                ItemKey::Unknown(k) => k.eq_ignore_ascii_case("rating"),
+                _ => false,
+            })
+            .and_then(|i| i.value().text())
+    })?;
+    parse_rating_scaled(s).or_else(|| s.trim().parse::<u8>().ok())
+}
+
+// Windows writes the rating (IRTD) as 0-5 stars, other taggers as 0-100
+fn parse_rating_riff(tag: &Tag) -> Option<u8> {
+    parse_rating_scaled(tag.get_string(&ItemKey::Popularimeter)?)
+}
+
+// AIFF has no rating chunk, so accept whatever scale was used (0-5, 0-100, or 0-255)
+// or look for an annotation such as "Rating: 80"
+fn parse_rating_aiff(tag: &Tag) -> Option<u8> {
+    if let Some(s) = tag.get_string(&ItemKey::Popularimeter) {
+        return parse_rating_scaled(s).or_else(|| s.trim().parse::<u8>().ok());
+    }
+    tag.get_strings(&ItemKey::Comment).find_map(|s| {
+        let s = s.trim();
+        let (key, value) = s.split_once(':').or_else(|| s.split_once('='))?;
+        if key.trim().eq_ignore_ascii_case("rating") {
+            parse_rating_scaled(value)
+        } else {
+            None
+        }
+    })
+}
+
+// Parse a rating that is either 0-5 stars or 0-100
+fn parse_rating_scaled(s: &str) -> Option<u8> {
+    let v = s.trim().parse::<u16>().ok()?;
+    match v {
+        0..=5 => Some((v * 51) as u8),
+        6..=100 => Some((v * 2 + v / 2) as u8),
+        _ => None,
+    }
+}
+
 fn parse_rating_text100(tag: &Tag) -> Option<u8> {
-    let s = tag.get_string(&ItemKey::Popularimeter)?;
-    let v = s.parse::<u8>().ok()?;
+    parse_rating_100(tag.get_string(&ItemKey::Popularimeter)?)
+}
+
+// Parse a rating on a 0-100 scale
+fn parse_rating_100(s: &str) -> Option<u8> {
+    let v = s.trim().parse::<u8>().ok().filter(|v| *v <= 100)?;
     Some(v * 2 + v / 2)
 }
 
-// Parse a path to try to guess the artist name
-fn parse_artist_from_path(path: &Path) -> String {
-    if let Some(parent) = path.parent() {
-        let (p1, p2) = parent.components().fold((None, None), |p, c| {
+// FLAC/Ogg files usually store the rating in a plain RATING comment (0-100)
+fn parse_rating_vorbis(tag: &Tag) -> Option<u8> {
+    let s = tag.get_string(&ItemKey::Popularimeter).or_else(|| {
+        tag.items()
+            .find(|i| match i.key() {
+                ItemKey::Unknown(k) => k.eq_ignore_ascii_case("rating"),
+                _ => false,
+            })
+            .and_then(|i| i.value().text())
+    })?;
+    parse_rating_100(s)
+}
+
+// ID3v2 stores the play count in the POPM counter or a PCNT frame, other formats use a text field
+fn parse_play_count(tag: &Tag) -> Option<u32> {
+    if tag.tag_type() == TagType::ID3v2 {
+        return tag
+            .items()
+            .find_map(|i| match (i.key(), i.value().binary()) {
+                (ItemKey::Popularimeter, Some(bin)) => {
+                    let end = bin.iter().position(|b| *b == 0)?;
+                    bin.get(end + 2..).filter(|c| !c.is_empty())
+                }
+                (ItemKey::Unknown(k), Some(bin)) if k == "PCNT" => Some(bin),
+                _ => None,
+            })
+            .map(|counter| {
+                // The counter is big-endian and can be longer than four bytes
+                let counter = counter.iter().fold(0u64, |c, b| (c << 8) | *b as u64);
+                counter.min(u32::MAX as u64) as u32
+            });
+    }
+    tag.items().find_map(|i| match i.key() {
+        ItemKey::Unknown(k)
+            if ["PLAY_COUNT", "PLAYCOUNT", "FMPS_PLAYCOUNT"]
+                .iter()
+                .any(|key| k.eq_ignore_ascii_case(key)) =>
+        {
+            // FMPS stores the count as a float
+            let count = i.value().text()?.trim().parse::<f64>().ok()?;
+            Some(count.max(0.0).min(u32::MAX as f64) as u32)
+        }
+        _ => None,
+    })
+}
+
+/// Guess the artist from the path of a file in the scanned directory `root`, either from an
+/// "artist/album/track" directory structure or (in flat folders and folders without albums)
+/// from an "Artist - Title" filename. Without a root only the path itself is used.
+pub fn guess_artist(path: &Path, root: Option<&Path>, separator: &str) -> String {
+    let relative = match root.and_then(|root| path.strip_prefix(root).ok()) {
+        Some(relative) => relative,
+        None => return parse_artist_from_path(path, separator),
+    };
+    let folders: Vec<_> = relative
+        .parent()
+        .map(|p| p.components())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    match folders.len() {
+        0 => parse_artist_from_filename(path, separator)
+            .map(String::from)
+            .unwrap_or_else(|| parse_artist_from_path(path, separator)),
+        1 => parse_artist_from_filename(path, separator)
+            .map(String::from)
+            .unwrap_or_else(|| folders[0].to_string_lossy().to_string()),
+        n => folders[n - 2].to_string_lossy().to_string(),
+    }
+}
+
+// Parse a path to try to guess the artist name, either from an "artist/album/track"
+// directory structure or (in flat folders) from an "Artist - Title" filename
+fn parse_artist_from_path(path: &Path, separator: &str) -> String {
+    let (p1, p2) = path.parent().map_or((None, None), |parent| {
+        parent.components().fold((None, None), |p, c| {
             if let Component::Normal(name) = c {
                 (Some(name), p.0)
             } else {
                 p
             }
-        });
-        if let Some(p) = p2 {
-            return p.to_string_lossy().to_string();
-        }
-        if let Some(p) = p1 {
-            return p.to_string_lossy().to_string();
-        }
+        })
+    });
+    if let Some(p) = p2 {
+        return p.to_string_lossy().to_string();
+    }
+    if let Some(artist) = parse_artist_from_filename(path, separator) {
+        return String::from(artist);
+    }
+    if let Some(p) = p1 {
+        return p.to_string_lossy().to_string();
     }
     String::new()
 }
 
+// The left side of an "Artist - Title" filename (unless it is a track number)
+fn parse_artist_from_filename<'a>(path: &'a Path, separator: &str) -> Option<&'a str> {
+    if separator.is_empty() {
+        return None;
+    }
+    let (artist, _) = path.file_stem()?.to_str()?.split_once(separator)?;
+    let artist = artist.trim();
+    if artist.is_empty() || artist.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        None
+    } else {
+        Some(artist)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use lofty::{ItemValue, TagItem};
     use std::path::PathBuf;
 
     use super::*;
 
     #[test]
     fn test_path_band() {
+        let parse = |path: &PathBuf| parse_artist_from_path(path, " - ");
         let path = PathBuf::from("a");
-        assert!(parse_artist_from_path(&path).is_empty());
+        assert!(parse(&path).is_empty());
         let path = path.join("b");
-        assert_eq!(parse_artist_from_path(&path), "a");
+        assert_eq!(parse(&path), "a");
         let path = path.join("c");
-        assert_eq!(parse_artist_from_path(&path), "a");
+        assert_eq!(parse(&path), "a");
         let path = path.join("d");
-        assert_eq!(parse_artist_from_path(&path), "b");
+        assert_eq!(parse(&path), "b");
+    }
+
+    #[test]
+    fn test_filename_band() {
+        let parse = |path: &str| parse_artist_from_path(Path::new(path), " - ");
+        assert_eq!(parse("Daft Punk - One More Time.mp3"), "Daft Punk");
+        assert_eq!(parse("Music/Daft Punk - One More Time.mp3"), "Daft Punk");
+        assert_eq!(parse("Justice - D.A.N.C.E. - Remix.mp3"), "Justice");
+        // The directory structure comes first
+        assert_eq!(parse("Artist/Album/Song - Live.mp3"), "Artist");
+        // Track numbers are not artists
+        assert_eq!(parse("a/01 - Title.mp3"), "a");
+        assert_eq!(parse("a/ - Title.mp3"), "a");
+        assert_eq!(parse("a/Title.mp3"), "a");
+        assert_eq!(parse("Title.mp3"), "");
+        let path = Path::new("a/Daft Punk_One More Time.mp3");
+        assert_eq!(parse_artist_from_path(path, " - "), "a");
+        assert_eq!(parse_artist_from_path(path, "_"), "Daft Punk");
+        assert_eq!(parse_artist_from_path(path, ""), "a");
+    }
+
+    #[test]
+    fn test_guess_artist() {
+        let root = Path::new("/home/user/Music");
+        let guess = |path: &str| guess_artist(&root.join(path), Some(root), " - ");
+        assert_eq!(guess("Daft Punk - One More Time.mp3"), "Daft Punk");
+        assert_eq!(guess("Artist/Album/Song - Live.mp3"), "Artist");
+        assert_eq!(guess("Genre/Artist/Album/Song.mp3"), "Artist");
+        // Without an album level the filename comes first
+        assert_eq!(guess("Singles/Daft Punk - One More Time.mp3"), "Daft Punk");
+        assert_eq!(guess("Artist/Song.mp3"), "Artist");
+        // Paths outside the root
+        let path = Path::new("/other/Artist/Album/Daft Punk - Song.mp3");
+        assert_eq!(guess_artist(path, Some(root), " - "), "Artist");
+        assert_eq!(guess_artist(path, None, " - "), "Artist");
+    }
+
+    #[test]
+    fn test_split_artists() {
+        let seps = TagConfig::default().artist_separators;
+        let split = |s| split_artists(s, &seps).join("|");
+        assert_eq!(
+            split("Daft Punk feat. Pharrell Williams"),
+            "Daft Punk|Pharrell Williams"
+        );
+        assert_eq!(
+            split("Mark Ronson (Feat. Bruno Mars)"),
+            "Mark Ronson|Bruno Mars"
+        );
+        assert_eq!(split("Calvin Harris x Dua Lipa"), "Calvin Harris|Dua Lipa");
+        assert_eq!(split("Artist A; Artist B;"), "Artist A|Artist B");
+        assert_eq!(split("Simon & Garfunkel"), "Simon|Garfunkel");
+        assert_eq!(split("AC/DC"), "AC/DC");
+        assert_eq!(split("Sigur Rós ft. Jónsi"), "Sigur Rós|Jónsi");
+        assert_eq!(split("Boxer"), "Boxer");
+        assert_eq!(split(""), "");
+        assert_eq!(
+            split_artists("Simon & Garfunkel / Someone", &[" / "]),
+            ["Simon & Garfunkel", "Someone"]
+        );
+    }
+
+    #[test]
+    fn test_rating_to_stars() {
+        for stars in 0..=5 {
+            assert_eq!(rating_to_stars(stars * 51), stars);
+        }
+        assert_eq!(rating_to_stars(200), 4);
+        assert_eq!(rating_to_stars(250), 5);
+        assert_eq!(rating_to_stars(1), 1);
+    }
+
+    #[test]
+    fn test_artist_keys() {
+        let mut tag = Tag::new(TagType::ID3v2);
+        tag.insert_text(ItemKey::TrackArtist, String::from("Orchestra"));
+        tag.insert_text(ItemKey::AlbumArtist, String::from("Album Artist"));
+        tag.insert_text(ItemKey::Composer, String::from("Composer"));
+        let artist = |keys: &str| {
+            let keys = TagConfig::parse_artist_keys(keys).unwrap();
+            parse_artist(&tag, &keys).map(String::from)
+        };
+        assert_eq!(
+            parse_artist(&tag, &TagConfig::default().artist_keys),
+            Some("Orchestra")
+        );
+        assert_eq!(
+            artist("composer,albumartist,trackartist").as_deref(),
+            Some("Composer")
+        );
+        assert_eq!(
+            artist("AlbumArtist, TrackArtist").as_deref(),
+            Some("Album Artist")
+        );
+        assert_eq!(
+            artist("conductor,trackartist").as_deref(),
+            Some("Orchestra")
+        );
+        assert_eq!(artist("conductor"), None);
+        let err = TagConfig::parse_artist_keys("composer,singer").unwrap_err();
+        assert!(err.contains("'singer'"));
+        assert_eq!(
+            TagConfig::default().artist_key_names(),
+            "trackartist,albumartist,originalartist,performer,composer"
+        );
+    }
+
+    #[test]
+    fn test_various_artists() {
+        let config = TagConfig::default();
+        assert!(config.is_various_artists("Various Artists"));
+        assert!(config.is_various_artists("VARIOUS ARTISTS"));
+        assert!(config.is_various_artists("Verschiedene Interpreten"));
+        assert!(!config.is_various_artists("Various Cruelties"));
+        let config = TagConfig {
+            various_artists: vec![String::from("Divers")],
+            ..Default::default()
+        };
+        assert!(config.is_various_artists("divers"));
+        assert!(!config.is_various_artists("Various Artists"));
+    }
+
+    #[test]
+    fn test_normalize_artist() {
+        assert_eq!(normalize_artist("The Beatles"), "beatles");
+        assert_eq!(normalize_artist("Beatles, The"), "beatles");
+        assert_eq!(normalize_artist("  THE   BEATLES "), "beatles");
+        assert_eq!(normalize_artist("beatles"), "beatles");
+        assert_eq!(normalize_artist("The The"), "the");
+        assert_eq!(normalize_artist("The"), "the");
+        assert_eq!(normalize_artist("Theatre of Tragedy"), "theatre of tragedy");
+        assert_eq!(normalize_artist("Sigur Rós"), "sigur ros");
+        assert_eq!(normalize_artist("MÖTLEY CRÜE"), "motley crue");
+        assert_eq!(normalize_artist("Beyoncé"), normalize_artist("BEYONCE"));
+        assert_eq!(normalize_artist("Mañá"), "mana");
+        assert_eq!(normalize_artist("Die Ärzte"), "die arzte");
+        assert_eq!(normalize_artist("坂本龍一"), "坂本龍一");
+        // Turkish dotted and dotless I
+        assert_eq!(normalize_artist("İSTANBUL"), "istanbul");
+        assert_eq!(normalize_artist("ıstanbul"), "istanbul");
+        assert_eq!(normalize_artist("Istanbul"), "istanbul");
+        assert_eq!(
+            normalize_artist("Sezen Aksu"),
+            normalize_artist("SEZEN AKSU")
+        );
+        // German sharp s (including the capital ẞ)
+        assert_eq!(normalize_artist("Die Ärzte Straße"), "die arzte strasse");
+        assert_eq!(normalize_artist("STRAẞE"), normalize_artist("strasse"));
+        // Precomposed and decomposed accents
+        assert_eq!(normalize_artist("Beyonce\u{301}"), "beyonce");
+        assert_eq!(
+            normalize_artist("Ólafur Arnalds"),
+            normalize_artist("O\u{301}lafur ARNALDS")
+        );
+        // Greek final sigma
+        assert_eq!(normalize_artist("ΟΔΥΣΣΕΑΣ"), normalize_artist("οδυσσεας"));
+        assert_eq!(normalize_artist(""), "");
+    }
+
+    #[test]
+    fn test_rating_ape() {
+        let rated = |value: &str| {
+            let mut tag = Tag::new(TagType::APE);
+            tag.insert_item_unchecked(TagItem::new(
+                ItemKey::Unknown(String::from("Rating")),
+                ItemValue::Text(String::from(value)),
+            ));
+            parse_rating(&tag)
+        };
+        assert_eq!(rated("80"), Some(200));
+        assert_eq!(rated("4"), Some(204));
+        assert_eq!(rated("0"), Some(0));
+        assert_eq!(rated("255"), Some(255));
+        assert_eq!(rated("153"), Some(153));
+        assert_eq!(rated("256"), None);
+        assert_eq!(rated("good"), None);
+        assert_eq!(parse_rating(&Tag::new(TagType::APE)), None);
+    }
+
+    #[test]
+    fn test_rating_wmp() {
+        let rated = |email: &str, rating: u8| {
+            let mut popm = email.as_bytes().to_vec();
+            popm.push(0);
+            popm.push(rating);
+            popm.extend(7u32.to_be_bytes());
+            let mut tag = Tag::new(TagType::ID3v2);
+            tag.insert_item_unchecked(TagItem::new(
+                ItemKey::Popularimeter,
+                ItemValue::Binary(popm),
+            ));
+            parse_rating(&tag)
+        };
+        let wmp = "Windows Media Player 9 Series";
+        assert_eq!(rated(wmp, 0), Some(0));
+        assert_eq!(rated(wmp, 1), Some(51));
+        assert_eq!(rated(wmp, 64), Some(102));
+        assert_eq!(rated(wmp, 128), Some(153));
+        assert_eq!(rated(wmp, 196), Some(204));
+        assert_eq!(rated(wmp, 255), Some(255));
+        // Other players use the byte as is
+        assert_eq!(rated("foo@bar.com", 1), Some(1));
+        assert_eq!(rated("foo@bar.com", 196), Some(196));
+        assert_eq!(rated("", 200), Some(200));
+    }
+
+    #[test]
+    fn test_id3v1() {
+        let mut tag = Tag::new(TagType::ID3v1);
+        tag.insert_text(ItemKey::TrackArtist, String::from("Artist"));
+        tag.insert_item_unchecked(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Binary(vec![0, 200, 0, 0, 0, 0]),
+        ));
+        assert_eq!(
+            parse_artist(&tag, &TagConfig::default().artist_keys),
+            Some("Artist")
+        );
+        assert_eq!(parse_rating(&tag), None);
+    }
+
+    #[test]
+    fn test_rating_riff() {
+        let rated = |value: &str| {
+            let mut tag = Tag::new(TagType::RIFFInfo);
+            assert!(tag.insert_text(ItemKey::Popularimeter, String::from(value)));
+            parse_rating(&tag)
+        };
+        assert_eq!(rated("80"), Some(200));
+        assert_eq!(rated("4"), Some(204));
+        assert_eq!(rated("5"), Some(255));
+        assert_eq!(rated("-1"), None);
+        assert_eq!(parse_rating(&Tag::new(TagType::RIFFInfo)), None);
+    }
+
+    #[test]
+    fn test_rating_aiff() {
+        let mut tag = Tag::new(TagType::AIFFText);
+        assert_eq!(parse_rating(&tag), None);
+        tag.insert_item_unchecked(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Text(String::from("230")),
+        ));
+        assert_eq!(parse_rating(&tag), Some(230));
+
+        let mut tag = Tag::new(TagType::AIFFText);
+        tag.insert_text(ItemKey::Comment, String::from("Recorded: live"));
+        assert_eq!(parse_rating(&tag), None);
+        tag.push_item(TagItem::new(
+            ItemKey::Comment,
+            ItemValue::Text(String::from("Rating: 80")),
+        ));
+        assert_eq!(parse_rating(&tag), Some(200));
+    }
+
+    #[test]
+    fn test_play_count() {
+        let popm = |counter: &[u8]| {
+            let mut bin = b"foo@bar.com\0\xc8".to_vec();
+            bin.extend(counter);
+            let mut tag = Tag::new(TagType::ID3v2);
+            tag.insert_item_unchecked(TagItem::new(ItemKey::Popularimeter, ItemValue::Binary(bin)));
+            parse_play_count(&tag)
+        };
+        assert_eq!(popm(&7u32.to_be_bytes()), Some(7));
+        assert_eq!(popm(&[1, 0, 0, 0, 0]), Some(u32::MAX));
+        assert_eq!(popm(&[]), None);
+        let mut tag = Tag::new(TagType::ID3v2);
+        tag.insert_item_unchecked(TagItem::new(
+            ItemKey::Unknown(String::from("PCNT")),
+            ItemValue::Binary(vec![0, 0, 1, 0]),
+        ));
+        assert_eq!(parse_play_count(&tag), Some(256));
+        let text = |key: &str, value: &str| {
+            let mut tag = Tag::new(TagType::VorbisComments);
+            tag.insert_item_unchecked(TagItem::new(
+                ItemKey::Unknown(String::from(key)),
+                ItemValue::Text(String::from(value)),
+            ));
+            parse_play_count(&tag)
+        };
+        assert_eq!(text("PLAY_COUNT", "12"), Some(12));
+        assert_eq!(text("playcount", " 3 "), Some(3));
+        assert_eq!(text("FMPS_PLAYCOUNT", "4.0"), Some(4));
+        assert_eq!(text("PLAY_COUNT", "often"), None);
+        assert_eq!(text("RATING", "12"), None);
+    }
+
+    #[test]
+    fn test_rating_vorbis() {
+        let rated = |key: ItemKey, value: &str| {
+            let mut tag = Tag::new(TagType::VorbisComments);
+            tag.insert_item_unchecked(TagItem::new(key, ItemValue::Text(String::from(value))));
+            parse_rating(&tag)
+        };
+        let raw = || ItemKey::Unknown(String::from("RATING"));
+        assert_eq!(rated(raw(), "80"), Some(200));
+        assert_eq!(rated(raw(), "100"), Some(250));
+        assert_eq!(rated(raw(), "0"), Some(0));
+        assert_eq!(rated(raw(), "101"), None);
+        assert_eq!(
+            rated(ItemKey::Unknown(String::from("rating")), "40"),
+            Some(100)
+        );
+        assert_eq!(rated(ItemKey::Popularimeter, "60"), Some(150));
+        let key = ItemKey::from_key(TagType::VorbisComments, "RATING");
+        assert_eq!(rated(key, "80"), Some(200));
+    }
+
+    #[test]
+    fn test_unrated() {
+        for tag_type in [
+            TagType::APE,
+            TagType::ID3v1,
+            TagType::ID3v2,
+            TagType::MP4ilst,
+            TagType::VorbisComments,
+            TagType::RIFFInfo,
+            TagType::AIFFText,
+        ] {
+            let mut tag = Tag::new(tag_type);
+            assert_eq!(parse_rating(&tag), None);
+            tag.insert_item_unchecked(TagItem::new(
+                ItemKey::Popularimeter,
+                ItemValue::Text(String::from("unrated")),
+            ));
+            assert_eq!(parse_rating(&tag), None);
+        }
+    }
+
+    // A one second silent WAV file with a RIFF INFO tag
+    fn write_wav(path: &Path, info: &[(&[u8; 4], &str)]) {
+        let chunk = |id: &[u8], data: &[u8]| {
+            let mut out = id.to_vec();
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend(data);
+            if data.len() % 2 == 1 {
+                out.push(0);
+            }
+            out
+        };
+        let mut fmt = Vec::new();
+        fmt.extend(1u16.to_le_bytes()); // PCM
+        fmt.extend(1u16.to_le_bytes()); // Mono
+        fmt.extend(8000u32.to_le_bytes()); // Sample rate
+        fmt.extend(8000u32.to_le_bytes()); // Byte rate
+        fmt.extend(1u16.to_le_bytes()); // Block align
+        fmt.extend(8u16.to_le_bytes()); // Bits per sample
+        let mut list = b"INFO".to_vec();
+        for (id, value) in info {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            list.extend(chunk(*id, &value));
+        }
+        let mut wave = b"WAVE".to_vec();
+        wave.extend(chunk(b"fmt ", &fmt));
+        wave.extend(chunk(b"data", &[128; 8000]));
+        wave.extend(chunk(b"LIST", &list));
+        std::fs::write(path, chunk(b"RIFF", &wave)).unwrap();
+    }
+
+    #[test]
+    fn test_wav_fixture() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_wav_fixture");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("track.wav");
+        write_wav(
+            &file,
+            &[
+                (b"IART", "Artist"),
+                (b"INAM", "Title"),
+                (b"IPRD", "Album"),
+                (b"IGNR", "Genre"),
+                (b"IRTD", "4"),
+                (b"ITRK", "3/12"),
+            ],
+        );
+        let info = get_tags(&file);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(info.artist, "Artist");
+        assert!(!info.guessed_artist);
+        assert_eq!(info.title.as_deref(), Some("Title"));
+        assert_eq!(info.album.as_deref(), Some("Album"));
+        assert_eq!(info.genre.as_deref(), Some("Genre"));
+        assert_eq!(info.rating, Some(204));
+        assert_eq!(info.track_number, Some(3));
+        assert_eq!(info.duration, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_guessed_artist() {
+        let dir = std::env::temp_dir().join("artistic_shuffle_test_guessed_artist");
+        std::fs::create_dir_all(&dir).unwrap();
+        let tagged = dir.join("Someone - Song.wav");
+        write_wav(&tagged, &[(b"IART", "Artist")]);
+        let tagless = dir.join("Band - Song.wav");
+        write_wav(&tagless, &[(b"INAM", "Song")]);
+        let infos = [get_tags(&tagged), get_tags(&tagless)];
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(infos[0].artist, "Artist");
+        let separator = TagConfig::default().filename_separator;
+        assert_eq!(
+            infos[1].artist,
+            parse_artist_from_path(&tagless, &separator)
+        );
+        assert_eq!(infos.iter().filter(|i| i.guessed_artist).count(), 1);
+        assert!(get_tags(Path::new("missing/file.mp3")).guessed_artist);
     }
 
     #[test]
@@ -116,7 +942,7 @@ mod tests {
             if let Ok(tags) = read_from_path(entry.path()) {
                 for tag in tags.tags() {
                     if let Err(e) = std::panic::catch_unwind(|| {
-                        parse_artist(tag);
+                        parse_artist(tag, &TagConfig::default().artist_keys);
                         assert!(!(2..15).contains(&parse_rating(tag).unwrap_or(100)));
                     }) {
                         dbg!(tag.items().collect::<Vec<_>>());
@@ -0,0 +1,47 @@
+use artistic_shuffle::{Counter, Playlist, Shuffler};
+use std::path::PathBuf;
+
+#[test]
+fn test_playlist() {
+    let mut pl = Playlist::new();
+    for artist in ["a", "b", "c", "d"] {
+        for i in 0..3 {
+            pl.add(
+                PathBuf::from(format!("{}{}", artist, i)),
+                artist.into(),
+                None,
+            );
+        }
+    }
+    assert_eq!(pl.len(), 12);
+    assert_eq!(pl.artist_count(), 4);
+    let shuffled = pl.shuffle_seeded(1);
+    let tracks: Vec<_> = shuffled.nested_iter().collect();
+    assert_eq!(tracks.len(), 12);
+    for pair in tracks.windows(2) {
+        assert_ne!(
+            pair[0].to_str().unwrap()[..1],
+            pair[1].to_str().unwrap()[..1]
+        );
+    }
+    let again: Vec<_> = pl.shuffle_seeded(1).nested_iter().copied().collect();
+    assert_eq!(tracks.into_iter().copied().collect::<Vec<_>>(), again);
+}
+
+#[test]
+fn test_shuffler() {
+    let mut counter = Counter::new();
+    counter.addn("a", 3);
+    counter.addn("b", 2);
+    counter.add("c");
+    let mut shuffler = Shuffler::new();
+    let mut counts: Vec<_> = counter.iter().collect();
+    counts.sort_unstable();
+    for (item, num) in counts {
+        shuffler.addn(*item, *num);
+    }
+    shuffler.shuffle_seeded(7, 10);
+    let items: Vec<_> = shuffler.iter().copied().collect();
+    assert_eq!(items.len(), counter.total());
+    assert!(items.windows(2).all(|w| w[0] != w[1]));
+}